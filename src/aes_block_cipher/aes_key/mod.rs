@@ -6,55 +6,40 @@ Represents both the expanded key (direct) and the inverse expanded key
  */
 use crate::aes_block_cipher::constants::{R_CON, S_BOX};
 use crate::aes_block_cipher::state::State;
-use crate::aes_block_cipher::{Word, N_B, N_K, N_R};
+use crate::aes_block_cipher::{KeySize, Word, MAX_N_R, N_B};
 
 pub struct AESKey {
-    pub data: [Word; N_B * (N_R + 1)],
+    pub data: [Word; N_B * (MAX_N_R + 1)],
 }
 
 impl AESKey {
-    pub fn new_direct(cipher_key: [u8; 4 * N_K as usize]) -> Self {
-        let mut data = [0; N_B * (N_R + 1)];
-        Self::expand_key(cipher_key, &mut data);
+    pub fn new_direct(cipher_key: &[u8], key_size: KeySize) -> Self {
+        let mut data = [0; N_B * (MAX_N_R + 1)];
+        Self::expand_key(cipher_key, key_size, &mut data);
         Self { data }
     }
 
-    pub fn new_inverse(cipher_key: [u8; 4 * N_K as usize]) -> Self {
-        let mut data = [0; N_B * (N_R + 1)];
-        Self::inv_expand_key(cipher_key, &mut data);
+    pub fn new_inverse(cipher_key: &[u8], key_size: KeySize) -> Self {
+        let mut data = [0; N_B * (MAX_N_R + 1)];
+        Self::inv_expand_key(cipher_key, key_size, &mut data);
         Self { data }
     }
 
-    fn expand_key(cipher_key: [u8; 4 * N_K as usize], data: &mut [Word; N_B * (N_R + 1)]) {
-        let mut temp: Word;
-        let mut i: usize = 0;
-
-        while i < N_K as usize {
-            data[i] = u32::from_be_bytes([
-                cipher_key[4 * i],
-                cipher_key[4 * i + 1],
-                cipher_key[4 * i + 2],
-                cipher_key[4 * i + 3],
-            ]);
-            i += 1;
-        }
-
-        i = N_K as usize;
+    /// Returns the `N_B` words of the round key used in the given round
+    pub fn round_key(&self, round: usize) -> &[Word; N_B] {
+        self.data[(round * N_B)..((round + 1) * N_B)]
+            .try_into()
+            .unwrap()
+    }
 
-        while i < (N_B * (N_R + 1)) {
-            temp = data[i - 1];
-            if i % N_K as usize == 0 {
-                temp = Self::sub_word(Self::rot_word(temp)) ^ R_CON[i / N_K as usize - 1];
-            }
-            data[i] = data[i - N_K as usize] ^ temp;
-            i += 1;
-        }
+    fn expand_key(cipher_key: &[u8], key_size: KeySize, data: &mut [Word; N_B * (MAX_N_R + 1)]) {
+        *data = expand_words(cipher_key, key_size, Self::sub_word);
     }
 
-    fn inv_expand_key(cipher_key: [u8; 4 * N_K as usize], dw: &mut [Word; N_B * (N_R + 1)]) {
-        Self::expand_key(cipher_key, dw);
+    fn inv_expand_key(cipher_key: &[u8], key_size: KeySize, dw: &mut [Word; N_B * (MAX_N_R + 1)]) {
+        Self::expand_key(cipher_key, key_size, dw);
 
-        for round in 1..N_R {
+        for round in 1..key_size.n_r() {
             let new_words = Self::inv_mix_columns_words(
                 &dw[round * N_B..(round + 1) * N_B].try_into().unwrap(),
             );
@@ -85,7 +70,7 @@ impl AESKey {
     permutation, and returns the word [a1, a2, a3, a0]
      */
     fn rot_word(word: Word) -> Word {
-        word << 8 | word >> 24
+        word.rotate_left(8)
     }
 
     fn inv_mix_columns_words(words: &[Word; N_B]) -> [Word; N_B] {
@@ -114,3 +99,47 @@ impl AESKey {
         S_BOX[pos_x * 16 + pos_y]
     }
 }
+
+/// Key expansion of FIPS-197 section 5.2, shared by the implementations of the cipher. SubWord
+/// is given by each of them, so that the constant-time ones do not look up the S-box table.
+/// Returns the words of the expanded key, of which only the first `N_B * (Nr + 1)` are used.
+fn expand_words(
+    cipher_key: &[u8],
+    key_size: KeySize,
+    sub_word: impl Fn(Word) -> Word,
+) -> [Word; N_B * (MAX_N_R + 1)] {
+    let n_k = key_size.n_k();
+    let mut words = [0; N_B * (MAX_N_R + 1)];
+    for (word, bytes) in words.iter_mut().zip(cipher_key.chunks_exact(4)) {
+        *word = Word::from_be_bytes(bytes.try_into().unwrap());
+    }
+
+    for i in n_k..N_B * (key_size.n_r() + 1) {
+        let mut temp = words[i - 1];
+        let word = i % n_k;
+        if word == 0 {
+            temp = sub_word(AESKey::rot_word(temp)) ^ R_CON[i / n_k - 1];
+        } else if n_k > 6 && word == 4 {
+            temp = sub_word(temp);
+        }
+        words[i] = words[i - n_k] ^ temp;
+    }
+    words
+}
+
+/// Round keys of the expanded key as blocks, for the implementations that cipher whole blocks
+/// at a time. Only the first Nr + 1 are used.
+pub(super) fn expand_round_keys(
+    cipher_key: &[u8],
+    key_size: KeySize,
+    sub_word: impl Fn(Word) -> Word,
+) -> [[u8; 4 * N_B]; MAX_N_R + 1] {
+    let words = expand_words(cipher_key, key_size, sub_word);
+    std::array::from_fn(|round| {
+        let mut round_key = [0; 4 * N_B];
+        for (c, word) in words[N_B * round..N_B * (round + 1)].iter().enumerate() {
+            round_key[4 * c..4 * c + 4].copy_from_slice(&word.to_be_bytes());
+        }
+        round_key
+    })
+}
@@ -0,0 +1,186 @@
+/*
+Hardware implementation of the cipher with the AES-NI instructions of x86_64 processors, each of
+which runs a whole round in constant time. Eight blocks are ciphered at once, with their rounds
+interleaved, so that the processor can start the round of a block while the previous ones are
+still in flight.
+ */
+use super::aes_key::expand_round_keys;
+use super::{BlockCipher, KeySize, Word, MAX_N_R, N_B};
+use crate::{Error, Result};
+use std::arch::x86_64::{
+    __m128i, _mm_aesdec_si128, _mm_aesdeclast_si128, _mm_aesenc_si128, _mm_aesenclast_si128,
+    _mm_aesimc_si128, _mm_aeskeygenassist_si128, _mm_cvtsi128_si32, _mm_loadu_si128, _mm_set_epi32,
+    _mm_storeu_si128, _mm_xor_si128,
+};
+
+/// Number of blocks ciphered at once
+const BLOCKS: usize = 8;
+
+/// Whether the processor has the AES-NI instructions
+pub fn is_supported() -> bool {
+    std::arch::is_x86_feature_detected!("aes")
+}
+
+pub struct AesNiBlockCipher {
+    round_keys: [__m128i; MAX_N_R + 1],
+    /// Round keys of the equivalent inverse cipher of FIPS-197 section 5.3.5, with InvMixColumns
+    /// applied to all but the first and last ones
+    inv_round_keys: [__m128i; MAX_N_R + 1],
+    key_size: KeySize,
+}
+
+impl AesNiBlockCipher {
+    /// Creates a block cipher for a 128, 192 or 256-bit key, selected by the key length. Fails
+    /// if the processor does not support AES-NI.
+    pub fn new(cipher_key: &[u8]) -> Result<Self> {
+        let key_size = KeySize::from_key_len(cipher_key.len())?;
+        if !is_supported() {
+            return Err(Error::InvalidConfig(
+                "the processor does not support AES-NI".to_string(),
+            ));
+        }
+
+        // SAFETY: AES-NI is supported, as checked above
+        let round_keys = expand_round_keys(cipher_key, key_size, |word| unsafe { sub_word(word) })
+            .map(|round_key| load(&round_key));
+        let mut inv_round_keys = round_keys;
+        for round in 1..key_size.n_r() {
+            // SAFETY: AES-NI is supported, as checked above
+            inv_round_keys[round] = unsafe { inv_mix_columns(round_keys[round]) };
+        }
+
+        Ok(Self {
+            round_keys,
+            inv_round_keys,
+            key_size,
+        })
+    }
+
+    #[target_feature(enable = "aes")]
+    fn cipher_group<const N: usize>(&self, blocks: &mut [[u8; 4 * N_B]; N]) {
+        let n_r = self.key_size.n_r();
+        let mut state = blocks.map(|block| _mm_xor_si128(load(&block), self.round_keys[0]));
+        for round_key in &self.round_keys[1..n_r] {
+            for s in state.iter_mut() {
+                *s = _mm_aesenc_si128(*s, *round_key);
+            }
+        }
+        for (block, s) in blocks.iter_mut().zip(state) {
+            store(block, _mm_aesenclast_si128(s, self.round_keys[n_r]));
+        }
+    }
+
+    #[target_feature(enable = "aes")]
+    fn inv_cipher_group<const N: usize>(&self, blocks: &mut [[u8; 4 * N_B]; N]) {
+        let n_r = self.key_size.n_r();
+        let mut state = blocks.map(|block| _mm_xor_si128(load(&block), self.inv_round_keys[n_r]));
+        for round_key in self.inv_round_keys[1..n_r].iter().rev() {
+            for s in state.iter_mut() {
+                *s = _mm_aesdec_si128(*s, *round_key);
+            }
+        }
+        for (block, s) in blocks.iter_mut().zip(state) {
+            store(block, _mm_aesdeclast_si128(s, self.inv_round_keys[0]));
+        }
+    }
+}
+
+/// SubWord of the key expansion, which AESKEYGENASSIST applies to the second word of its input
+/// and returns as the first word of its output
+#[target_feature(enable = "aes")]
+fn sub_word(word: Word) -> Word {
+    let input = _mm_set_epi32(0, 0, word as i32, 0);
+    _mm_cvtsi128_si32(_mm_aeskeygenassist_si128::<0>(input)) as Word
+}
+
+#[target_feature(enable = "aes")]
+fn inv_mix_columns(round_key: __m128i) -> __m128i {
+    _mm_aesimc_si128(round_key)
+}
+
+fn load(block: &[u8; 4 * N_B]) -> __m128i {
+    // SAFETY: the block has 16 bytes, and the unaligned load has no alignment requirement
+    unsafe { _mm_loadu_si128(block.as_ptr().cast()) }
+}
+
+fn store(block: &mut [u8; 4 * N_B], value: __m128i) {
+    // SAFETY: the block has 16 bytes, and the unaligned store has no alignment requirement
+    unsafe { _mm_storeu_si128(block.as_mut_ptr().cast(), value) }
+}
+
+impl BlockCipher for AesNiBlockCipher {
+    fn key_size(&self) -> KeySize {
+        self.key_size
+    }
+
+    fn parallel_blocks(&self) -> usize {
+        BLOCKS
+    }
+
+    fn cipher_block_in_place(&self, block: &mut [u8; 4 * N_B]) {
+        // SAFETY: the block cipher is only created if AES-NI is supported
+        unsafe { self.cipher_group(std::array::from_mut(block)) }
+    }
+
+    fn inv_cipher_block_in_place(&self, block: &mut [u8; 4 * N_B]) {
+        // SAFETY: the block cipher is only created if AES-NI is supported
+        unsafe { self.inv_cipher_group(std::array::from_mut(block)) }
+    }
+
+    fn cipher_blocks(&self, blocks: &mut [[u8; 4 * N_B]]) {
+        let (groups, remainder) = blocks.as_chunks_mut::<BLOCKS>();
+        // SAFETY: the block cipher is only created if AES-NI is supported
+        groups
+            .iter_mut()
+            .for_each(|group| unsafe { self.cipher_group(group) });
+        remainder
+            .iter_mut()
+            .for_each(|block| self.cipher_block_in_place(block));
+    }
+
+    fn inv_cipher_blocks(&self, blocks: &mut [[u8; 4 * N_B]]) {
+        let (groups, remainder) = blocks.as_chunks_mut::<BLOCKS>();
+        // SAFETY: the block cipher is only created if AES-NI is supported
+        groups
+            .iter_mut()
+            .for_each(|group| unsafe { self.inv_cipher_group(group) });
+        remainder
+            .iter_mut()
+            .for_each(|block| self.inv_cipher_block_in_place(block));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes_block_cipher::AESBlockCipher;
+    use rand::Rng;
+
+    #[test]
+    fn test_matches_reference_implementation() {
+        if !is_supported() {
+            return;
+        }
+
+        let mut rng = rand::thread_rng();
+        for key_len in [16, 24, 32] {
+            let mut cipher_key = vec![0; key_len];
+            rng.fill(cipher_key.as_mut_slice());
+            let reference = AESBlockCipher::new(&cipher_key).unwrap();
+            let aes_ni = AesNiBlockCipher::new(&cipher_key).unwrap();
+
+            // Not a multiple of the eight blocks ciphered at once
+            let mut blocks: Vec<[u8; 16]> = (0..63).map(|_| rng.gen()).collect();
+            let plain_blocks = blocks.clone();
+            let expected_blocks: Vec<[u8; 16]> = blocks
+                .iter()
+                .map(|block| reference.cipher_block(block))
+                .collect();
+
+            aes_ni.cipher_blocks(&mut blocks);
+            assert_eq!(blocks, expected_blocks);
+            aes_ni.inv_cipher_blocks(&mut blocks);
+            assert_eq!(blocks, plain_blocks);
+        }
+    }
+}
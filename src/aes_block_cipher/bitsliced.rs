@@ -0,0 +1,481 @@
+/*
+Constant-time bitsliced implementation of the cipher, which processes four blocks at a time.
+The blocks are transposed into eight 64-bit words, where word i holds bit i of every byte: bit
+16 * row + 4 * column + k is the bit of the byte at that row and column of block k. SubBytes is
+the Boyar-Peralta circuit of 113 logic gates, ShiftRows and MixColumns are shifts and rotations
+of the words, and the key schedule uses the same S-box, so no memory access or branch depends on
+the key or the data.
+ */
+use super::aes_key::expand_round_keys;
+use super::{BlockCipher, KeySize, Word, MAX_N_R, N_B};
+
+/// Number of blocks ciphered at once
+const BLOCKS: usize = 4;
+
+/// Bit planes of four blocks
+type Planes = [u64; 8];
+
+pub struct BitslicedBlockCipher {
+    /// Each round key repeated for the four blocks, already transposed
+    round_keys: [Planes; MAX_N_R + 1],
+    key_size: KeySize,
+}
+
+impl BitslicedBlockCipher {
+    /// Creates a block cipher for a 128, 192 or 256-bit key, selected by the key length
+    pub fn new(cipher_key: &[u8]) -> crate::Result<Self> {
+        let key_size = KeySize::from_key_len(cipher_key.len())?;
+        let round_keys = expand_round_keys(cipher_key, key_size, sub_word)
+            .map(|round_key| load_blocks(&[round_key; BLOCKS]));
+
+        Ok(Self {
+            round_keys,
+            key_size,
+        })
+    }
+
+    fn cipher_planes(&self, q: &mut Planes) {
+        let n_r = self.key_size.n_r();
+        add_round_key(q, &self.round_keys[0]);
+        for round in 1..n_r {
+            sub_bytes(q);
+            shift_rows(q);
+            mix_columns(q);
+            add_round_key(q, &self.round_keys[round]);
+        }
+        sub_bytes(q);
+        shift_rows(q);
+        add_round_key(q, &self.round_keys[n_r]);
+    }
+
+    /// Inverse cipher of FIPS-197 section 5.3, which uses the same round keys as the cipher
+    fn inv_cipher_planes(&self, q: &mut Planes) {
+        let n_r = self.key_size.n_r();
+        add_round_key(q, &self.round_keys[n_r]);
+        for round in (1..n_r).rev() {
+            inv_shift_rows(q);
+            inv_sub_bytes(q);
+            add_round_key(q, &self.round_keys[round]);
+            inv_mix_columns(q);
+        }
+        inv_shift_rows(q);
+        inv_sub_bytes(q);
+        add_round_key(q, &self.round_keys[0]);
+    }
+
+    /// Run `f` on the planes of each group of four blocks. The last group is padded with zero
+    /// blocks, whose output is discarded.
+    fn for_each_group(&self, blocks: &mut [[u8; 4 * N_B]], f: impl Fn(&Self, &mut Planes)) {
+        for group in blocks.chunks_mut(BLOCKS) {
+            let mut padded = [[0; 4 * N_B]; BLOCKS];
+            padded[..group.len()].copy_from_slice(group);
+            let mut q = load_blocks(&padded);
+            f(self, &mut q);
+            store_blocks(q, &mut padded);
+            group.copy_from_slice(&padded[..group.len()]);
+        }
+    }
+}
+
+/// SubWord of the key expansion, computed by the bitsliced S-box so that it runs in constant time
+fn sub_word(word: Word) -> Word {
+    let mut blocks = [[0; 4 * N_B]; BLOCKS];
+    blocks[0][..4].copy_from_slice(&word.to_be_bytes());
+    let mut q = load_blocks(&blocks);
+    sub_bytes(&mut q);
+    store_blocks(q, &mut blocks);
+    Word::from_be_bytes(blocks[0][..4].try_into().unwrap())
+}
+
+/// Spread the four little-endian columns of a block, so that the bytes of row r are at bits
+/// 16 * r (columns 0 and 1) and 16 * r + 8 (columns 2 and 3) of the two words
+fn interleave_in(block: &[u8; 4 * N_B]) -> (u64, u64) {
+    let [mut x0, mut x1, mut x2, mut x3]: [u64; N_B] = std::array::from_fn(|c| {
+        u32::from_le_bytes(block[4 * c..4 * c + 4].try_into().unwrap()) as u64
+    });
+    for x in [&mut x0, &mut x1, &mut x2, &mut x3] {
+        *x |= *x << 16;
+        *x &= 0x0000ffff0000ffff;
+        *x |= *x << 8;
+        *x &= 0x00ff00ff00ff00ff;
+    }
+    (x0 | (x2 << 8), x1 | (x3 << 8))
+}
+
+fn interleave_out(q0: u64, q1: u64, block: &mut [u8; 4 * N_B]) {
+    let mut x = [
+        q0 & 0x00ff00ff00ff00ff,
+        q1 & 0x00ff00ff00ff00ff,
+        (q0 >> 8) & 0x00ff00ff00ff00ff,
+        (q1 >> 8) & 0x00ff00ff00ff00ff,
+    ];
+    for (c, x) in x.iter_mut().enumerate() {
+        *x |= *x >> 8;
+        *x &= 0x0000ffff0000ffff;
+        let column = *x as u32 | (*x >> 16) as u32;
+        block[4 * c..4 * c + 4].copy_from_slice(&column.to_le_bytes());
+    }
+}
+
+/// Transpose the 8x8 bit matrices formed by the bytes at the same position of the eight words.
+/// The transposition is its own inverse.
+fn ortho(q: &mut Planes) {
+    fn swap(q: &mut Planes, i: usize, j: usize, low_mask: u64, shift: u32) {
+        let (a, b) = (q[i], q[j]);
+        q[i] = (a & low_mask) | ((b & low_mask) << shift);
+        q[j] = ((a >> shift) & low_mask) | (b & !low_mask);
+    }
+
+    for (distance, low_mask, shift) in [
+        (1, 0x5555555555555555, 1),
+        (2, 0x3333333333333333, 2),
+        (4, 0x0f0f0f0f0f0f0f0f, 4),
+    ] {
+        for i in (0..8).filter(|i| i & distance == 0) {
+            swap(q, i, i + distance, low_mask, shift);
+        }
+    }
+}
+
+fn load_blocks(blocks: &[[u8; 4 * N_B]; BLOCKS]) -> Planes {
+    let mut q = [0; 8];
+    for (k, block) in blocks.iter().enumerate() {
+        (q[k], q[k + BLOCKS]) = interleave_in(block);
+    }
+    ortho(&mut q);
+    q
+}
+
+fn store_blocks(mut q: Planes, blocks: &mut [[u8; 4 * N_B]; BLOCKS]) {
+    ortho(&mut q);
+    for (k, block) in blocks.iter_mut().enumerate() {
+        interleave_out(q[k], q[k + BLOCKS], block);
+    }
+}
+
+fn add_round_key(q: &mut Planes, round_key: &Planes) {
+    q.iter_mut()
+        .zip(round_key)
+        .for_each(|(plane, key_plane)| *plane ^= key_plane);
+}
+
+/// S-box circuit from "A new combinational logic minimization technique with applications to
+/// cryptology", Boyar and Peralta, 2009. The input bits x0 to x7 go from the most to the least
+/// significant bit.
+fn sub_bytes(q: &mut Planes) {
+    let [x7, x6, x5, x4, x3, x2, x1, x0] = *q;
+
+    // Top linear transformation
+    let y14 = x3 ^ x5;
+    let y13 = x0 ^ x6;
+    let y9 = x0 ^ x3;
+    let y8 = x0 ^ x5;
+    let t0 = x1 ^ x2;
+    let y1 = t0 ^ x7;
+    let y4 = y1 ^ x3;
+    let y12 = y13 ^ y14;
+    let y2 = y1 ^ x0;
+    let y5 = y1 ^ x6;
+    let y3 = y5 ^ y8;
+    let t1 = x4 ^ y12;
+    let y15 = t1 ^ x5;
+    let y20 = t1 ^ x1;
+    let y6 = y15 ^ x7;
+    let y10 = y15 ^ t0;
+    let y11 = y20 ^ y9;
+    let y7 = x7 ^ y11;
+    let y17 = y10 ^ y11;
+    let y19 = y10 ^ y8;
+    let y16 = t0 ^ y11;
+    let y21 = y13 ^ y16;
+    let y18 = x0 ^ y16;
+
+    // Non-linear section, which computes the inverse in GF(2^8)
+    let t2 = y12 & y15;
+    let t3 = y3 & y6;
+    let t4 = t3 ^ t2;
+    let t5 = y4 & x7;
+    let t6 = t5 ^ t2;
+    let t7 = y13 & y16;
+    let t8 = y5 & y1;
+    let t9 = t8 ^ t7;
+    let t10 = y2 & y7;
+    let t11 = t10 ^ t7;
+    let t12 = y9 & y11;
+    let t13 = y14 & y17;
+    let t14 = t13 ^ t12;
+    let t15 = y8 & y10;
+    let t16 = t15 ^ t12;
+    let t17 = t4 ^ t14;
+    let t18 = t6 ^ t16;
+    let t19 = t9 ^ t14;
+    let t20 = t11 ^ t16;
+    let t21 = t17 ^ y20;
+    let t22 = t18 ^ y19;
+    let t23 = t19 ^ y21;
+    let t24 = t20 ^ y18;
+
+    let t25 = t21 ^ t22;
+    let t26 = t21 & t23;
+    let t27 = t24 ^ t26;
+    let t28 = t25 & t27;
+    let t29 = t28 ^ t22;
+    let t30 = t23 ^ t24;
+    let t31 = t22 ^ t26;
+    let t32 = t31 & t30;
+    let t33 = t32 ^ t24;
+    let t34 = t23 ^ t33;
+    let t35 = t27 ^ t33;
+    let t36 = t24 & t35;
+    let t37 = t36 ^ t34;
+    let t38 = t27 ^ t36;
+    let t39 = t29 & t38;
+    let t40 = t25 ^ t39;
+
+    let t41 = t40 ^ t37;
+    let t42 = t29 ^ t33;
+    let t43 = t29 ^ t40;
+    let t44 = t33 ^ t37;
+    let t45 = t42 ^ t41;
+    let z0 = t44 & y15;
+    let z1 = t37 & y6;
+    let z2 = t33 & x7;
+    let z3 = t43 & y16;
+    let z4 = t40 & y1;
+    let z5 = t29 & y7;
+    let z6 = t42 & y11;
+    let z7 = t45 & y17;
+    let z8 = t41 & y10;
+    let z9 = t44 & y12;
+    let z10 = t37 & y3;
+    let z11 = t33 & y4;
+    let z12 = t43 & y13;
+    let z13 = t40 & y5;
+    let z14 = t29 & y2;
+    let z15 = t42 & y9;
+    let z16 = t45 & y14;
+    let z17 = t41 & y8;
+
+    // Bottom linear transformation, which includes the affine transformation
+    let t46 = z15 ^ z16;
+    let t47 = z10 ^ z11;
+    let t48 = z5 ^ z13;
+    let t49 = z9 ^ z10;
+    let t50 = z2 ^ z12;
+    let t51 = z2 ^ z5;
+    let t52 = z7 ^ z8;
+    let t53 = z0 ^ z3;
+    let t54 = z6 ^ z7;
+    let t55 = z16 ^ z17;
+    let t56 = z12 ^ t48;
+    let t57 = t50 ^ t53;
+    let t58 = z4 ^ t46;
+    let t59 = z3 ^ t54;
+    let t60 = t46 ^ t57;
+    let t61 = z14 ^ t57;
+    let t62 = t52 ^ t58;
+    let t63 = t49 ^ t58;
+    let t64 = z4 ^ t59;
+    let t65 = t61 ^ t62;
+    let t66 = z1 ^ t63;
+    let s0 = t59 ^ t63;
+    let s6 = t56 ^ !t62;
+    let s7 = t48 ^ !t60;
+    let t67 = t64 ^ t65;
+    let s3 = t53 ^ t66;
+    let s4 = t51 ^ t66;
+    let s5 = t47 ^ t65;
+    let s1 = t64 ^ !s3;
+    let s2 = t55 ^ !t67;
+
+    *q = [s7, s6, s5, s4, s3, s2, s1, s0];
+}
+
+/// Inverse of the affine transformation of FIPS-197 section 5.1.1
+fn inv_affine(q: &mut Planes) {
+    let [q0, q1, q2, q3, q4, q5, q6, q7] = *q;
+    let (q0, q1, q5, q6) = (!q0, !q1, !q5, !q6);
+    *q = [
+        q2 ^ q5 ^ q7,
+        q3 ^ q6 ^ q0,
+        q4 ^ q7 ^ q1,
+        q5 ^ q0 ^ q2,
+        q6 ^ q1 ^ q3,
+        q7 ^ q2 ^ q4,
+        q0 ^ q3 ^ q5,
+        q1 ^ q4 ^ q6,
+    ];
+}
+
+/// The S-box is the affine transformation of the inverse, so the inverse S-box is the S-box
+/// surrounded by the inverse affine transformation
+fn inv_sub_bytes(q: &mut Planes) {
+    inv_affine(q);
+    sub_bytes(q);
+    inv_affine(q);
+}
+
+/// Rotate row r left by r columns, which are groups of four bits within the 16 bits of the row
+fn shift_rows(q: &mut Planes) {
+    for x in q.iter_mut() {
+        *x = (*x & 0x000000000000ffff)
+            | ((*x & 0x00000000fff00000) >> 4)
+            | ((*x & 0x00000000000f0000) << 12)
+            | ((*x & 0x0000ff0000000000) >> 8)
+            | ((*x & 0x000000ff00000000) << 8)
+            | ((*x & 0xf000000000000000) >> 12)
+            | ((*x & 0x0fff000000000000) << 4);
+    }
+}
+
+fn inv_shift_rows(q: &mut Planes) {
+    for x in q.iter_mut() {
+        *x = (*x & 0x000000000000ffff)
+            | ((*x & 0x000000000fff0000) << 4)
+            | ((*x & 0x00000000f0000000) >> 12)
+            | ((*x & 0x0000ff0000000000) >> 8)
+            | ((*x & 0x000000ff00000000) << 8)
+            | ((*x & 0x000f000000000000) << 12)
+            | ((*x & 0xfff0000000000000) >> 4);
+    }
+}
+
+/// Multiplication by x of every byte, as `xtime` in FIPS-197 section 4.2.1
+fn xtime(q: &Planes) -> Planes {
+    [
+        q[7],
+        q[0] ^ q[7],
+        q[1],
+        q[2] ^ q[7],
+        q[3] ^ q[7],
+        q[4],
+        q[5],
+        q[6],
+    ]
+}
+
+/// Rotating a word by 16 and 32 bits moves every row of the columns up by one and two rows, so
+/// each byte becomes {02}a_r + {03}a_r+1 + a_r+2 + a_r+3 = {02}(a_r + a_r+1) + a_r+1 + (a_r+2
+/// + a_r+3).
+fn mix_columns(q: &mut Planes) {
+    let r: Planes = q.map(|x| x.rotate_right(16));
+    let a: Planes = std::array::from_fn(|i| q[i] ^ r[i]);
+    let doubled = xtime(&a);
+    *q = std::array::from_fn(|i| doubled[i] ^ r[i] ^ a[i].rotate_right(32));
+}
+
+/// The InvMixColumns matrix is the MixColumns matrix times the one with rows
+/// {05} {00} {04} {00}, so each byte first becomes {05}a_r + {04}a_r+2 = a_r + {04}(a_r + a_r+2)
+fn inv_mix_columns(q: &mut Planes) {
+    let a: Planes = q.map(|x| x ^ x.rotate_right(32));
+    let quadrupled = xtime(&xtime(&a));
+    q.iter_mut()
+        .zip(quadrupled)
+        .for_each(|(plane, product)| *plane ^= product);
+    mix_columns(q);
+}
+
+impl BlockCipher for BitslicedBlockCipher {
+    fn key_size(&self) -> KeySize {
+        self.key_size
+    }
+
+    fn parallel_blocks(&self) -> usize {
+        BLOCKS
+    }
+
+    fn cipher_block_in_place(&self, block: &mut [u8; 4 * N_B]) {
+        self.cipher_blocks(std::slice::from_mut(block));
+    }
+
+    fn inv_cipher_block_in_place(&self, block: &mut [u8; 4 * N_B]) {
+        self.inv_cipher_blocks(std::slice::from_mut(block));
+    }
+
+    fn cipher_blocks(&self, blocks: &mut [[u8; 4 * N_B]]) {
+        self.for_each_group(blocks, Self::cipher_planes);
+    }
+
+    fn inv_cipher_blocks(&self, blocks: &mut [[u8; 4 * N_B]]) {
+        self.for_each_group(blocks, Self::inv_cipher_planes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes_block_cipher::constants::{INV_S_BOX, S_BOX};
+    use crate::aes_block_cipher::AESBlockCipher;
+    use rand::Rng;
+
+    #[test]
+    fn test_layout() {
+        let mut rng = rand::thread_rng();
+        let blocks: [[u8; 16]; BLOCKS] = rng.gen();
+        let q = load_blocks(&blocks);
+
+        for (i, plane) in q.iter().enumerate() {
+            for (k, block) in blocks.iter().enumerate() {
+                for (position, byte) in block.iter().enumerate() {
+                    let (row, column) = (position % 4, position / 4);
+                    let bit = plane >> (16 * row + 4 * column + k) & 1;
+                    assert_eq!(bit, (*byte as u64 >> i) & 1);
+                }
+            }
+        }
+
+        let mut stored = [[0; 16]; BLOCKS];
+        store_blocks(q, &mut stored);
+        assert_eq!(stored, blocks);
+    }
+
+    #[test]
+    fn test_s_box() {
+        for group in 0..256 / (4 * N_B * BLOCKS) {
+            let mut blocks: [[u8; 16]; BLOCKS] =
+                std::array::from_fn(|k| std::array::from_fn(|i| (64 * group + 16 * k + i) as u8));
+            let mut q = load_blocks(&blocks);
+            sub_bytes(&mut q);
+            store_blocks(q, &mut blocks);
+            assert!(blocks
+                .as_flattened()
+                .iter()
+                .enumerate()
+                .all(|(i, byte)| *byte == S_BOX[64 * group + i]));
+
+            inv_sub_bytes(&mut q);
+            store_blocks(q, &mut blocks);
+            assert!(blocks
+                .as_flattened()
+                .iter()
+                .enumerate()
+                .all(|(i, byte)| *byte == INV_S_BOX[S_BOX[64 * group + i] as usize]));
+        }
+        assert_eq!(sub_word(0xcf4f3c09), 0x8a84eb01);
+    }
+
+    #[test]
+    fn test_matches_reference_implementation() {
+        let mut rng = rand::thread_rng();
+        for key_len in [16, 24, 32] {
+            let mut cipher_key = vec![0; key_len];
+            rng.fill(cipher_key.as_mut_slice());
+            let reference = AESBlockCipher::new(&cipher_key).unwrap();
+            let bitsliced = BitslicedBlockCipher::new(&cipher_key).unwrap();
+
+            // Not a multiple of the four blocks ciphered at once
+            let mut blocks: Vec<[u8; 16]> = (0..63).map(|_| rng.gen()).collect();
+            let plain_blocks = blocks.clone();
+            let expected_blocks: Vec<[u8; 16]> = blocks
+                .iter()
+                .map(|block| reference.cipher_block(block))
+                .collect();
+
+            bitsliced.cipher_blocks(&mut blocks);
+            assert_eq!(blocks, expected_blocks);
+            bitsliced.inv_cipher_blocks(&mut blocks);
+            assert_eq!(blocks, plain_blocks);
+            assert_eq!(bitsliced.cipher_block(&plain_blocks[0]), expected_blocks[0]);
+        }
+    }
+}
@@ -1,103 +1,304 @@
-use std::convert::TryInto;
-
 #[cfg(test)]
 mod tests;
 
 mod aes_key;
+#[cfg(target_arch = "x86_64")]
+mod aes_ni;
+mod bitsliced;
 mod constants;
 pub mod state;
+mod t_table;
 
+use crate::{Error, Result};
 use aes_key::AESKey;
 
+#[cfg(target_arch = "x86_64")]
+pub use aes_ni::AesNiBlockCipher;
+
+pub use bitsliced::BitslicedBlockCipher;
 use state::State;
+pub use t_table::TTableBlockCipher;
 
 // Number of columns (32-bit words) comprising the State
-pub const N_K: u8 = 4;
-// Number of 32-bit words comprising the Cipher Key
 pub const N_B: usize = 4;
-// Number of rounds, which is a function of Nk and Nb (which is fixed)
-pub const N_R: usize = 10;
+// Maximum number of rounds, used by AES-256
+pub const MAX_N_R: usize = 14;
+// Maximum number of blocks any implementation ciphers at once
+pub const MAX_PARALLEL_BLOCKS: usize = 8;
 
 pub type Word = u32;
 
+/// Key lengths supported by the block cipher, as defined in FIPS-197 section 5
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySize {
+    Aes128,
+    Aes192,
+    Aes256,
+}
+
+impl KeySize {
+    /// Returns the key size matching a cipher key of `len` bytes
+    pub fn from_key_len(len: usize) -> Result<Self> {
+        match len {
+            16 => Ok(KeySize::Aes128),
+            24 => Ok(KeySize::Aes192),
+            32 => Ok(KeySize::Aes256),
+            _ => Err(Error::InvalidKey(format!(
+                "the key is {} bytes long (expected 16, 24 or 32)",
+                len
+            ))),
+        }
+    }
+
+    /// Number of 32-bit words comprising the Cipher Key (Nk)
+    pub fn n_k(&self) -> usize {
+        match self {
+            KeySize::Aes128 => 4,
+            KeySize::Aes192 => 6,
+            KeySize::Aes256 => 8,
+        }
+    }
+
+    /// Number of rounds, which is a function of Nk and Nb (which is fixed)
+    pub fn n_r(&self) -> usize {
+        self.n_k() + 6
+    }
+
+    /// Length of the Cipher Key in bytes
+    pub fn key_len(&self) -> usize {
+        4 * self.n_k()
+    }
+}
+
+impl std::str::FromStr for KeySize {
+    type Err = String;
+
+    /// Parses a key size in bits, optionally prefixed with `aes`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s
+            .to_ascii_lowercase()
+            .trim_start_matches("aes")
+            .trim_start_matches('-')
+        {
+            "128" => Ok(KeySize::Aes128),
+            "192" => Ok(KeySize::Aes192),
+            "256" => Ok(KeySize::Aes256),
+            _ => Err(format!(
+                "Unknown key size: {} (expected 128, 192 or 256)",
+                s
+            )),
+        }
+    }
+}
+
+/// AES with a fixed key, implemented by each of the block cipher backends
+pub trait BlockCipher: Send + Sync {
+    fn key_size(&self) -> KeySize;
+
+    /// Number of blocks the implementation ciphers at once, so `cipher_blocks` is best called
+    /// with a multiple of it. At most `MAX_PARALLEL_BLOCKS`.
+    fn parallel_blocks(&self) -> usize {
+        1
+    }
+
+    /// Cipher the block, overwriting it with the ciphertext
+    fn cipher_block_in_place(&self, block: &mut [u8; 4 * N_B]);
+
+    /// Decipher the block, overwriting it with the plaintext
+    fn inv_cipher_block_in_place(&self, block: &mut [u8; 4 * N_B]);
+
+    fn cipher_block(&self, data_in: &[u8; 4 * N_B]) -> [u8; 4 * N_B] {
+        let mut data_out = *data_in;
+        self.cipher_block_in_place(&mut data_out);
+        data_out
+    }
+
+    fn inv_cipher_block(&self, data_in: &[u8; 4 * N_B]) -> [u8; 4 * N_B] {
+        let mut data_out = *data_in;
+        self.inv_cipher_block_in_place(&mut data_out);
+        data_out
+    }
+
+    /// Cipher each of the blocks independently and in place, as in ECB mode
+    fn cipher_blocks(&self, blocks: &mut [[u8; 4 * N_B]]) {
+        blocks
+            .iter_mut()
+            .for_each(|block| self.cipher_block_in_place(block));
+    }
+
+    /// Decipher each of the blocks independently and in place, as in ECB mode
+    fn inv_cipher_blocks(&self, blocks: &mut [[u8; 4 * N_B]]) {
+        blocks
+            .iter_mut()
+            .for_each(|block| self.inv_cipher_block_in_place(block));
+    }
+}
+
+/// Implementations of the block cipher, which produce the same output at different speeds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Implementation {
+    /// AES-NI if the processor supports it, and otherwise the T-table implementation, the
+    /// fastest portable one
+    Auto,
+    /// `AESBlockCipher`, which follows the steps of FIPS-197 on a byte matrix
+    Reference,
+    /// `TTableBlockCipher`, which merges the steps of each round into table lookups
+    TTable,
+    /// `BitslicedBlockCipher`, which runs in constant time on four blocks at a time
+    Bitsliced,
+    /// `AesNiBlockCipher`, which uses the AES instructions of x86_64 processors
+    AesNi,
+}
+
+impl Implementation {
+    /// Name of the implementation, as used in the configuration and the metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Implementation::Auto => "auto",
+            Implementation::Reference => "reference",
+            Implementation::TTable => "ttable",
+            Implementation::Bitsliced => "bitsliced",
+            Implementation::AesNi => "aesni",
+        }
+    }
+
+    /// Whether the implementation can run on this processor
+    pub fn is_supported(&self) -> bool {
+        match self {
+            Implementation::AesNi => aes_ni_supported(),
+            _ => true,
+        }
+    }
+
+    /// The implementation `Auto` stands for on this processor, which is detected at runtime.
+    /// The other implementations are returned as they are.
+    pub fn resolve(&self) -> Implementation {
+        match self {
+            Implementation::Auto if aes_ni_supported() => Implementation::AesNi,
+            Implementation::Auto => Implementation::TTable,
+            implementation => *implementation,
+        }
+    }
+
+    /// Creates a block cipher of this implementation for a 128, 192 or 256-bit key
+    pub fn new_block_cipher(&self, cipher_key: &[u8]) -> Result<Box<dyn BlockCipher>> {
+        Ok(match self.resolve() {
+            Implementation::Reference => Box::new(AESBlockCipher::new(cipher_key)?),
+            Implementation::TTable => Box::new(TTableBlockCipher::new(cipher_key)?),
+            Implementation::Bitsliced => Box::new(BitslicedBlockCipher::new(cipher_key)?),
+            #[cfg(target_arch = "x86_64")]
+            Implementation::AesNi => Box::new(AesNiBlockCipher::new(cipher_key)?),
+            #[cfg(not(target_arch = "x86_64"))]
+            Implementation::AesNi => {
+                return Err(Error::InvalidConfig(
+                    "AES-NI is only available on x86_64".to_string(),
+                ))
+            }
+            Implementation::Auto => unreachable!("resolved to an implementation"),
+        })
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn aes_ni_supported() -> bool {
+    aes_ni::is_supported()
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn aes_ni_supported() -> bool {
+    false
+}
+
+impl std::str::FromStr for Implementation {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Implementation::Auto),
+            "reference" => Ok(Implementation::Reference),
+            "ttable" | "t-table" => Ok(Implementation::TTable),
+            "bitsliced" => Ok(Implementation::Bitsliced),
+            "aesni" | "aes-ni" => Ok(Implementation::AesNi),
+            _ => Err(format!(
+                "Unknown AES implementation: {} (expected auto, reference, ttable, bitsliced or aesni)",
+                s
+            )),
+        }
+    }
+}
+
 pub struct AESBlockCipher {
     expanded_key: AESKey,
     inv_expanded_key: AESKey,
+    key_size: KeySize,
 }
 
 impl AESBlockCipher {
-    pub fn new(cipher_key: [u8; 4 * N_B]) -> Self {
-        let expanded_key = AESKey::new_direct(cipher_key);
-        let inv_expanded_key = AESKey::new_inverse(cipher_key);
+    /// Creates a block cipher for a 128, 192 or 256-bit key, selected by the key length
+    pub fn new(cipher_key: &[u8]) -> Result<Self> {
+        let key_size = KeySize::from_key_len(cipher_key.len())?;
+        Ok(Self::new_with_key_size(cipher_key, key_size))
+    }
+
+    pub fn new_u128(cipher_key: u128) -> Self {
+        let cipher_key_bytes = cipher_key.to_be_bytes();
+        Self::new_with_key_size(&cipher_key_bytes, KeySize::Aes128)
+    }
+
+    fn new_with_key_size(cipher_key: &[u8], key_size: KeySize) -> Self {
+        let expanded_key = AESKey::new_direct(cipher_key, key_size);
+        let inv_expanded_key = AESKey::new_inverse(cipher_key, key_size);
 
         Self {
             expanded_key,
             inv_expanded_key,
+            key_size,
         }
     }
+}
 
-    pub fn new_u128(cipher_key: u128) -> Self {
-        let cipher_key_bytes = cipher_key.to_be_bytes();
-        Self::new(cipher_key_bytes)
+impl BlockCipher for AESBlockCipher {
+    fn key_size(&self) -> KeySize {
+        self.key_size
     }
 
-    pub fn cipher_block(&self, data_in: &[u8; 4 * N_B]) -> [u8; 4 * N_B] {
-        let mut data_out = [0; 4 * N_B];
+    fn cipher_block_in_place(&self, block: &mut [u8; 4 * N_B]) {
+        let n_r = self.key_size.n_r();
 
-        let mut state = State::new_from_data_in(data_in);
+        let mut state = State::new_from_data_in(block);
 
-        state.add_round_key(&self.expanded_key.data[0..N_B].try_into().unwrap());
+        state.add_round_key(self.expanded_key.round_key(0));
 
-        for round in 1..N_R {
+        for round in 1..n_r {
             state.sub_bytes();
             state.shift_rows();
             state.mix_columns();
-            state.add_round_key(
-                &self.expanded_key.data[(round * N_B)..((round + 1) * N_B)]
-                    .try_into()
-                    .unwrap(),
-            );
+            state.add_round_key(self.expanded_key.round_key(round));
         }
         state.sub_bytes();
         state.shift_rows();
-        state.add_round_key(
-            &self.expanded_key.data[(N_R * N_B)..((N_R + 1) * N_B)]
-                .try_into()
-                .unwrap(),
-        );
+        state.add_round_key(self.expanded_key.round_key(n_r));
 
-        state.set_data_out(&mut data_out);
-
-        data_out
+        state.set_data_out(block);
     }
 
-    pub fn inv_cipher_block(&self, data_in: &[u8; 4 * N_B]) -> [u8; 4 * N_B] {
-        let mut data_out = [0; 4 * N_B];
+    fn inv_cipher_block_in_place(&self, block: &mut [u8; 4 * N_B]) {
+        let n_r = self.key_size.n_r();
 
-        let mut state = State::new_from_data_in(data_in);
+        let mut state = State::new_from_data_in(block);
 
-        state.add_round_key(
-            &self.inv_expanded_key.data[(N_R * N_B)..((N_R + 1) * N_B)]
-                .try_into()
-                .unwrap(),
-        );
+        state.add_round_key(self.inv_expanded_key.round_key(n_r));
 
-        for round in (1..N_R).rev() {
+        for round in (1..n_r).rev() {
             state.inv_sub_bytes();
             state.inv_shift_rows();
             state.inv_mix_columns();
-            state.add_round_key(
-                &self.inv_expanded_key.data[(round * N_B)..((round + 1) * N_B)]
-                    .try_into()
-                    .unwrap(),
-            );
+            state.add_round_key(self.inv_expanded_key.round_key(round));
         }
         state.inv_sub_bytes();
         state.inv_shift_rows();
-        state.add_round_key(&self.inv_expanded_key.data[0..N_B].try_into().unwrap());
+        state.add_round_key(self.inv_expanded_key.round_key(0));
 
-        state.set_data_out(&mut data_out);
-
-        data_out
+        state.set_data_out(block);
     }
 }
@@ -11,6 +11,12 @@ pub struct State {
     pub data: Matrix,
 }
 
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl State {
     pub fn new() -> Self {
         Self {
@@ -138,7 +144,7 @@ impl State {
         col[1] = Self::galois_dobule((b ^ c) as i8) ^ c ^ d ^ a;
         col[2] = Self::galois_dobule((c ^ d) as i8) ^ d ^ a ^ b;
         col[3] = Self::galois_dobule((d ^ a) as i8) ^ a ^ b ^ c;
-    }   
+    }
 
     fn inv_mix_column(col: &mut [u8; 4]) {
         let a = col[0];
@@ -154,7 +160,6 @@ impl State {
         col[3] = Self::galois_dobule((z ^ d ^ a) as i8) ^ a ^ b ^ c;
     }
 
-
     #[inline]
     fn galois_dobule(a: i8) -> u8 {
         let mut result = (a << 1) as u8;
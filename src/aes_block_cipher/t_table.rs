@@ -0,0 +1,237 @@
+/*
+Table-based implementation of the cipher. SubBytes, ShiftRows and MixColumns are merged into
+four lookups per column and round in tables of 256 32-bit words, which are computed at compile
+time from the S-box. The state is kept as four big-endian column words, as in the key schedule.
+ */
+use super::aes_key::AESKey;
+use super::constants::{INV_S_BOX, S_BOX};
+use super::{BlockCipher, KeySize, Word, N_B};
+
+/// Multiplication by x in GF(2^8), as defined in FIPS-197 section 4.2.1
+const fn xtime(b: u8) -> u8 {
+    (b << 1) ^ (((b >> 7) & 1) * 0x1b)
+}
+
+const fn gf_mul(a: u8, b: u8) -> u8 {
+    let mut a = a;
+    let mut b = b;
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        a = xtime(a);
+        b >>= 1;
+    }
+    product
+}
+
+/// Builds the four tables of a round: the first maps a byte to its substituted and mixed column
+/// `coefficients`, and each of the others is the previous one rotated by a byte.
+const fn round_tables(s_box: &[u8; 256], coefficients: [u8; 4]) -> [[Word; 256]; 4] {
+    let mut tables = [[0; 256]; 4];
+    let mut i = 0;
+    while i < 256 {
+        let s = s_box[i];
+        let word = u32::from_be_bytes([
+            gf_mul(s, coefficients[0]),
+            gf_mul(s, coefficients[1]),
+            gf_mul(s, coefficients[2]),
+            gf_mul(s, coefficients[3]),
+        ]);
+        tables[0][i] = word;
+        tables[1][i] = word.rotate_right(8);
+        tables[2][i] = word.rotate_right(16);
+        tables[3][i] = word.rotate_right(24);
+        i += 1;
+    }
+    tables
+}
+
+/// Columns of the MixColumns matrix, applied to the output of SubBytes
+static TE: [[Word; 256]; 4] = round_tables(&S_BOX, [0x02, 0x01, 0x01, 0x03]);
+/// Columns of the InvMixColumns matrix, applied to the output of InvSubBytes
+static TD: [[Word; 256]; 4] = round_tables(&INV_S_BOX, [0x0e, 0x09, 0x0d, 0x0b]);
+
+pub struct TTableBlockCipher {
+    expanded_key: AESKey,
+    inv_expanded_key: AESKey,
+    key_size: KeySize,
+}
+
+impl TTableBlockCipher {
+    /// Creates a block cipher for a 128, 192 or 256-bit key, selected by the key length
+    pub fn new(cipher_key: &[u8]) -> crate::Result<Self> {
+        let key_size = KeySize::from_key_len(cipher_key.len())?;
+        Ok(Self {
+            expanded_key: AESKey::new_direct(cipher_key, key_size),
+            inv_expanded_key: AESKey::new_inverse(cipher_key, key_size),
+            key_size,
+        })
+    }
+}
+
+fn load_state(block: &[u8; 4 * N_B], round_key: &[Word; N_B]) -> [Word; N_B] {
+    std::array::from_fn(|c| {
+        u32::from_be_bytes(block[4 * c..4 * c + 4].try_into().unwrap()) ^ round_key[c]
+    })
+}
+
+fn store_state(state: [Word; N_B], block: &mut [u8; 4 * N_B]) {
+    for (c, word) in state.iter().enumerate() {
+        block[4 * c..4 * c + 4].copy_from_slice(&word.to_be_bytes());
+    }
+}
+
+fn byte(word: Word, row: usize) -> usize {
+    (word >> (24 - 8 * row)) as usize & 0xff
+}
+
+impl BlockCipher for TTableBlockCipher {
+    fn key_size(&self) -> KeySize {
+        self.key_size
+    }
+
+    fn cipher_block_in_place(&self, block: &mut [u8; 4 * N_B]) {
+        let n_r = self.key_size.n_r();
+        let mut s = load_state(block, self.expanded_key.round_key(0));
+
+        // Row r of column c comes from column c + r, as in ShiftRows
+        for round in 1..n_r {
+            let round_key = self.expanded_key.round_key(round);
+            s = std::array::from_fn(|c| {
+                TE[0][byte(s[c], 0)]
+                    ^ TE[1][byte(s[(c + 1) % N_B], 1)]
+                    ^ TE[2][byte(s[(c + 2) % N_B], 2)]
+                    ^ TE[3][byte(s[(c + 3) % N_B], 3)]
+                    ^ round_key[c]
+            });
+        }
+
+        let round_key = self.expanded_key.round_key(n_r);
+        let s: [Word; N_B] = std::array::from_fn(|c| {
+            u32::from_be_bytes([
+                S_BOX[byte(s[c], 0)],
+                S_BOX[byte(s[(c + 1) % N_B], 1)],
+                S_BOX[byte(s[(c + 2) % N_B], 2)],
+                S_BOX[byte(s[(c + 3) % N_B], 3)],
+            ]) ^ round_key[c]
+        });
+        store_state(s, block);
+    }
+
+    /// Equivalent inverse cipher of FIPS-197 section 5.3.5, whose round keys have
+    /// InvMixColumns applied so that the rounds have the same structure as in the cipher
+    fn inv_cipher_block_in_place(&self, block: &mut [u8; 4 * N_B]) {
+        let n_r = self.key_size.n_r();
+        let mut s = load_state(block, self.inv_expanded_key.round_key(n_r));
+
+        // Row r of column c comes from column c - r, as in InvShiftRows
+        for round in (1..n_r).rev() {
+            let round_key = self.inv_expanded_key.round_key(round);
+            s = std::array::from_fn(|c| {
+                TD[0][byte(s[c], 0)]
+                    ^ TD[1][byte(s[(c + 3) % N_B], 1)]
+                    ^ TD[2][byte(s[(c + 2) % N_B], 2)]
+                    ^ TD[3][byte(s[(c + 1) % N_B], 3)]
+                    ^ round_key[c]
+            });
+        }
+
+        let round_key = self.inv_expanded_key.round_key(0);
+        let s: [Word; N_B] = std::array::from_fn(|c| {
+            u32::from_be_bytes([
+                INV_S_BOX[byte(s[c], 0)],
+                INV_S_BOX[byte(s[(c + 3) % N_B], 1)],
+                INV_S_BOX[byte(s[(c + 2) % N_B], 2)],
+                INV_S_BOX[byte(s[(c + 1) % N_B], 3)],
+            ]) ^ round_key[c]
+        });
+        store_state(s, block);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes_block_cipher::AESBlockCipher;
+    use rand::Rng;
+
+    #[test]
+    fn test_tables() {
+        // FIPS-197 section 4.2: {57} x {13} = {fe}
+        assert_eq!(gf_mul(0x57, 0x13), 0xfe);
+        // S-box of 0x00 is 0x63, and {02} x {63} = {c6}, {03} x {63} = {a5}
+        assert_eq!(TE[0][0], 0xc66363a5);
+        assert_eq!(TE[3][0], 0x6363a5c6);
+        // Inverse S-box of 0x00 is 0x52
+        assert_eq!(
+            TD[0][0],
+            u32::from_be_bytes([
+                gf_mul(0x52, 0x0e),
+                gf_mul(0x52, 0x09),
+                gf_mul(0x52, 0x0d),
+                gf_mul(0x52, 0x0b)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_fips_197_example_vectors() {
+        let plain_block: [u8; 16] = std::array::from_fn(|i| (i as u8) * 0x11);
+        let vectors: [(usize, [u8; 16]); 3] = [
+            (
+                16,
+                [
+                    0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70,
+                    0xb4, 0xc5, 0x5a,
+                ],
+            ),
+            (
+                24,
+                [
+                    0xdd, 0xa9, 0x7c, 0xa4, 0x86, 0x4c, 0xdf, 0xe0, 0x6e, 0xaf, 0x70, 0xa0, 0xec,
+                    0x0d, 0x71, 0x91,
+                ],
+            ),
+            (
+                32,
+                [
+                    0x8e, 0xa2, 0xb7, 0xca, 0x51, 0x67, 0x45, 0xbf, 0xea, 0xfc, 0x49, 0x90, 0x4b,
+                    0x49, 0x60, 0x89,
+                ],
+            ),
+        ];
+
+        for (key_len, cipher_block) in vectors {
+            let cipher_key: Vec<u8> = (0..key_len as u8).collect();
+            let cipher = TTableBlockCipher::new(&cipher_key).unwrap();
+
+            assert_eq!(cipher.cipher_block(&plain_block), cipher_block);
+            assert_eq!(cipher.inv_cipher_block(&cipher_block), plain_block);
+        }
+    }
+
+    #[test]
+    fn test_matches_reference_implementation() {
+        let mut rng = rand::thread_rng();
+        for key_len in [16, 24, 32] {
+            let mut cipher_key = vec![0; key_len];
+            rng.fill(cipher_key.as_mut_slice());
+            let reference = AESBlockCipher::new(&cipher_key).unwrap();
+            let t_table = TTableBlockCipher::new(&cipher_key).unwrap();
+
+            let mut blocks: Vec<[u8; 16]> = (0..64).map(|_| rng.gen()).collect();
+            let plain_blocks = blocks.clone();
+            let expected_blocks: Vec<[u8; 16]> = blocks
+                .iter()
+                .map(|block| reference.cipher_block(block))
+                .collect();
+
+            t_table.cipher_blocks(&mut blocks);
+            assert_eq!(blocks, expected_blocks);
+            t_table.inv_cipher_blocks(&mut blocks);
+            assert_eq!(blocks, plain_blocks);
+        }
+    }
+}
@@ -1,5 +1,17 @@
 use super::*;
 
+/// Every implementation that runs on this processor
+fn implementations() -> impl Iterator<Item = Implementation> {
+    [
+        Implementation::Reference,
+        Implementation::TTable,
+        Implementation::Bitsliced,
+        Implementation::AesNi,
+    ]
+    .into_iter()
+    .filter(Implementation::is_supported)
+}
+
 #[test]
 fn test_shift_rows() {
     let mut state = State::new_from_data([
@@ -166,7 +178,7 @@ fn test_cipher() {
         0x34,
     ];
 
-    let cipher_key: [u8; 4 * N_K as usize] = [
+    let cipher_key: [u8; 16] = [
         0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f,
         0x3c,
     ];
@@ -176,12 +188,14 @@ fn test_cipher() {
         0x32,
     ];
 
-    let cipher = AESBlockCipher::new(cipher_key);
+    for implementation in implementations() {
+        let cipher = implementation.new_block_cipher(&cipher_key).unwrap();
 
-    let cipher_bytes = cipher.cipher_block(&plain_bytes);
+        let cipher_bytes = cipher.cipher_block(&plain_bytes);
 
-    for i in 0..(N_B * 4) {
-        assert_eq!(cipher_bytes[i], expected_cipher_bytes[i]);
+        for i in 0..(N_B * 4) {
+            assert_eq!(cipher_bytes[i], expected_cipher_bytes[i]);
+        }
     }
 }
 
@@ -238,7 +252,7 @@ fn test_inv_cipher() {
         0x34,
     ];
 
-    let cipher_key: [u8; 4 * N_K as usize] = [
+    let cipher_key: [u8; 16] = [
         0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f,
         0x3c,
     ];
@@ -248,11 +262,138 @@ fn test_inv_cipher() {
         0x32,
     ];
 
-    let cipher = AESBlockCipher::new(cipher_key);
+    for implementation in implementations() {
+        let cipher = implementation.new_block_cipher(&cipher_key).unwrap();
 
-    let plain_text = cipher.inv_cipher_block(&cipher_bytes);
+        let plain_text = cipher.inv_cipher_block(&cipher_bytes);
 
-    for i in 0..(N_B * 4) {
-        assert_eq!(plain_text[i], expected_plain_text[i]);
+        for i in 0..(N_B * 4) {
+            assert_eq!(plain_text[i], expected_plain_text[i]);
+        }
+    }
+}
+
+#[test]
+fn test_cipher_aes_128_example_vector() {
+    let plain_bytes: [u8; 4 * N_B] = [
+        0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+        0xff,
+    ];
+
+    let cipher_key: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+
+    let expected_cipher_bytes: [u8; 4 * N_B] = [
+        0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4, 0xc5,
+        0x5a,
+    ];
+
+    for implementation in implementations() {
+        let cipher = implementation.new_block_cipher(&cipher_key).unwrap();
+
+        assert_eq!(cipher.cipher_block(&plain_bytes), expected_cipher_bytes);
+        assert_eq!(cipher.inv_cipher_block(&expected_cipher_bytes), plain_bytes);
     }
 }
+
+#[test]
+fn test_cipher_aes_192_example_vector() {
+    let plain_bytes: [u8; 4 * N_B] = [
+        0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+        0xff,
+    ];
+
+    let cipher_key: [u8; 24] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
+    ];
+
+    let expected_cipher_bytes: [u8; 4 * N_B] = [
+        0xdd, 0xa9, 0x7c, 0xa4, 0x86, 0x4c, 0xdf, 0xe0, 0x6e, 0xaf, 0x70, 0xa0, 0xec, 0x0d, 0x71,
+        0x91,
+    ];
+
+    for implementation in implementations() {
+        let cipher = implementation.new_block_cipher(&cipher_key).unwrap();
+
+        assert_eq!(cipher.cipher_block(&plain_bytes), expected_cipher_bytes);
+        assert_eq!(cipher.inv_cipher_block(&expected_cipher_bytes), plain_bytes);
+    }
+}
+
+#[test]
+fn test_cipher_aes_256_example_vector() {
+    let plain_bytes: [u8; 4 * N_B] = [
+        0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+        0xff,
+    ];
+
+    let cipher_key: [u8; 32] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
+        0x1e, 0x1f,
+    ];
+
+    let expected_cipher_bytes: [u8; 4 * N_B] = [
+        0x8e, 0xa2, 0xb7, 0xca, 0x51, 0x67, 0x45, 0xbf, 0xea, 0xfc, 0x49, 0x90, 0x4b, 0x49, 0x60,
+        0x89,
+    ];
+
+    for implementation in implementations() {
+        let cipher = implementation.new_block_cipher(&cipher_key).unwrap();
+
+        assert_eq!(cipher.cipher_block(&plain_bytes), expected_cipher_bytes);
+        assert_eq!(cipher.inv_cipher_block(&expected_cipher_bytes), plain_bytes);
+    }
+}
+
+#[test]
+fn test_new_rejects_invalid_key_length() {
+    for implementation in implementations() {
+        assert!(implementation.new_block_cipher(&[0u8; 20]).is_err());
+    }
+}
+
+#[test]
+fn test_parse_implementation() {
+    for implementation in implementations() {
+        assert_eq!(implementation.name().parse(), Ok(implementation));
+    }
+    assert_eq!("auto".parse(), Ok(Implementation::Auto));
+    assert_eq!("T-Table".parse(), Ok(Implementation::TTable));
+    assert!(matches!(
+        Implementation::Auto.resolve(),
+        Implementation::AesNi | Implementation::TTable
+    ));
+    assert!("openssl".parse::<Implementation>().is_err());
+}
+
+#[test]
+fn test_cipher_blocks_in_place() {
+    let cipher_key: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+    let cipher = AESBlockCipher::new(&cipher_key).unwrap();
+
+    let plain_blocks: Vec<[u8; 4 * N_B]> =
+        (0..5u8).map(|i| [i.wrapping_mul(37); 4 * N_B]).collect();
+    let expected_blocks: Vec<[u8; 4 * N_B]> = plain_blocks
+        .iter()
+        .map(|block| cipher.cipher_block(block))
+        .collect();
+
+    let mut block = plain_blocks[0];
+    cipher.cipher_block_in_place(&mut block);
+    assert_eq!(block, expected_blocks[0]);
+    cipher.inv_cipher_block_in_place(&mut block);
+    assert_eq!(block, plain_blocks[0]);
+
+    let mut blocks = plain_blocks.clone();
+    cipher.cipher_blocks(&mut blocks);
+    assert_eq!(blocks, expected_blocks);
+    cipher.inv_cipher_blocks(&mut blocks);
+    assert_eq!(blocks, plain_blocks);
+}
@@ -1,114 +1,1617 @@
-use crate::aes_block_cipher::{AESBlockCipher, N_B};
-use crate::utils::{ChunkReader, ChunkWriter};
-use crate::BUFFER_SIZE;
-use rayon::prelude::*;
+use crate::aes_block_cipher::{BlockCipher, Implementation, KeySize, N_B};
+use crate::concurrency::{self, Backend, Executor};
+use crate::container::Header;
+use crate::kdf::KdfParams;
+use crate::modes::ctr::CounterLayout;
+use crate::modes::gcm::{self, GcmState};
+use crate::modes::{cbc, ctr, Mode};
+use crate::padding::{Padding, PaddingScheme};
+use crate::utils::{
+    regular_file, unpadded_len, ChunkReader, ChunkWriter, Output, PhaseTimer, Pipeline,
+};
+pub use crate::utils::{Phase, PhaseTimes};
+use crate::{Error, Result};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+#[cfg(unix)]
+use std::os::unix::fs::FileExt;
+use std::time::Instant;
+
+/// Number of blocks read, ciphered and written at a time by default, which is 128 KiB
+pub const DEFAULT_BUFFER_BLOCKS: usize = 8192;
+/// Largest number of blocks in the buffer, which is 1 GiB
+pub const MAX_BUFFER_BLOCKS: usize = 1 << 26;
+
+/// Settings of an `AESCipher`, besides its key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AESCipherConfig {
+    /// Number of threads that cipher the blocks in parallel. With 0, the number of CPUs is
+    /// used, which `AESCipher::config` then reports.
+    pub n_threads: usize,
+    /// Threading model used to cipher the blocks in parallel
+    pub backend: Backend,
+    /// Minimum number of blocks in each task that the backend runs in parallel. Smaller tasks
+    /// balance the work better between the threads, and larger ones have less overhead. Tasks
+    /// also hold at least the blocks the implementation ciphers at once.
+    pub task_blocks: usize,
+    /// Implementation of the block cipher. `Auto` is resolved when the cipher is created, so
+    /// `AESCipher::config` returns the implementation in use.
+    pub implementation: Implementation,
+    /// Mode of operation used when ciphering. Deciphering uses the mode in the file header.
+    pub mode: Mode,
+    /// Padding scheme used when ciphering in ECB or CBC mode
+    pub padding: PaddingScheme,
+    /// Number of 16-byte blocks processed at a time, from 1 to `MAX_BUFFER_BLOCKS`. A pipelined
+    /// cipher allocates two buffers of this size.
+    pub buffer_blocks: usize,
+    /// Read and write the files on their own threads, overlapping with the ciphering of the
+    /// previous batch, instead of reading, ciphering and writing one batch at a time
+    pub pipelined: bool,
+    /// Map regular files into memory in `cipher_file` and `decipher_file`, and cipher them all at
+    /// once instead of in batches. Other files, such as pipes, are still streamed. The files must
+    /// not be modified by other processes while they are being ciphered.
+    pub mmap: bool,
+    /// On Unix, cipher regular files in ECB and CTR modes with each task reading its own part of
+    /// the input with `read_at` and writing it with `write_at` into the preallocated output, so
+    /// that the file I/O runs in parallel too. Takes effect after `mmap`, and other files and
+    /// modes are still streamed.
+    pub positional_io: bool,
+}
+
+impl Default for AESCipherConfig {
+    fn default() -> Self {
+        Self {
+            n_threads: 1,
+            backend: Backend::Rayon,
+            task_blocks: 1,
+            implementation: Implementation::Auto,
+            mode: Mode::Ecb,
+            padding: PaddingScheme::Pkcs7,
+            buffer_blocks: DEFAULT_BUFFER_BLOCKS,
+            pipelined: false,
+            mmap: false,
+            positional_io: false,
+        }
+    }
+}
 
 pub struct AESCipher {
-    block_cipher: AESBlockCipher,
-    thread_pool: rayon::ThreadPool,
-    buffer: Vec<[u8; 4 * N_B]>,
+    block_cipher: Box<dyn BlockCipher>,
+    executor: Box<dyn Executor>,
+    pipeline: Pipeline,
+    /// Copy of the ciphertext of the batch being deciphered in CBC mode, which is allocated the
+    /// first time it is needed and then reused
+    ciphertext_buffer: Vec<[u8; 4 * N_B]>,
+    config: AESCipherConfig,
+    derived_key: Option<DerivedKey>,
+}
+
+/// Passphrase the key of the block cipher was derived from, and the parameters used
+struct DerivedKey {
+    passphrase: Vec<u8>,
+    kdf: KdfParams,
 }
 
 impl AESCipher {
-    pub fn new(cipher_key: u128, n_threads: usize) -> Result<Self, String> {
-        let block_cipher = AESBlockCipher::new_u128(cipher_key);
-        let thread_pool = Self::create_thread_pool(n_threads)?;
+    /// Creates a cipher for a 128, 192 or 256-bit key, selected by the key length
+    pub fn new(cipher_key: &[u8], config: AESCipherConfig) -> Result<Self> {
+        Self::new_with_key(cipher_key, None, config)
+    }
+
+    /// Creates a cipher whose key is derived from a passphrase with PBKDF2-HMAC-SHA256 and a
+    /// random salt. The salt and iteration count are stored in the header of the ciphered
+    /// files, so the key is derived again when deciphering a file with different ones.
+    pub fn new_with_passphrase(
+        passphrase: &[u8],
+        key_size: KeySize,
+        iterations: u32,
+        config: AESCipherConfig,
+    ) -> Result<Self> {
+        let kdf = KdfParams::new_random(iterations)?;
+        let cipher_key = kdf.derive_key(passphrase, key_size);
+        let derived_key = DerivedKey {
+            passphrase: passphrase.to_vec(),
+            kdf,
+        };
+        Self::new_with_key(&cipher_key, Some(derived_key), config)
+    }
+
+    fn new_with_key(
+        cipher_key: &[u8],
+        derived_key: Option<DerivedKey>,
+        mut config: AESCipherConfig,
+    ) -> Result<Self> {
+        if !(1..=MAX_BUFFER_BLOCKS).contains(&config.buffer_blocks) {
+            return Err(Error::InvalidConfig(format!(
+                "the buffer must hold from 1 to {} blocks ({} requested)",
+                MAX_BUFFER_BLOCKS, config.buffer_blocks
+            )));
+        }
+        if config.task_blocks == 0 {
+            return Err(Error::InvalidConfig(
+                "each task must hold at least one block".to_string(),
+            ));
+        }
+
+        config.implementation = config.implementation.resolve();
+        config.n_threads = concurrency::resolve_n_threads(config.n_threads);
+        let block_cipher = config.implementation.new_block_cipher(cipher_key)?;
+        let executor = config
+            .backend
+            .executor(config.n_threads, config.task_blocks)?;
         let ret = Self {
             block_cipher,
-            thread_pool,
-            buffer: vec![[0; 4 * N_B]; BUFFER_SIZE],
+            executor,
+            pipeline: Pipeline::new(config.buffer_blocks, config.pipelined),
+            ciphertext_buffer: Vec::new(),
+            config,
+            derived_key,
         };
         Ok(ret)
     }
 
-    fn create_thread_pool(n_threads: usize) -> Result<rayon::ThreadPool, String> {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(n_threads)
-            .build()
-            .map_err(|e| format!("Error while creating thread pool: {}", e))
+    pub fn config(&self) -> &AESCipherConfig {
+        &self.config
     }
 
-    fn cipher_blocks(&self, chunks: &[[u8; 4 * N_B]]) -> Vec<[u8; 4 * N_B]> {
-        self.thread_pool.install(|| {
-            chunks
-                .par_iter()
-                .map(|block| self.block_cipher.cipher_block(block))
-                .collect::<Vec<_>>()
-        })
+    /// Return the time spent reading the input, ciphering and writing the output since the
+    /// last call, added up over every file ciphered or deciphered, and start again from zero
+    pub fn take_phase_times(&self) -> PhaseTimes {
+        self.phase_timer().take()
     }
 
-    fn decipher_blocks(&self, chunks: &[[u8; 4 * N_B]]) -> Vec<[u8; 4 * N_B]> {
-        self.thread_pool.install(|| {
-            chunks
-                .par_iter()
-                .map(|block| self.block_cipher.inv_cipher_block(block))
-                .collect::<Vec<_>>()
-        })
+    fn phase_timer(&self) -> &PhaseTimer {
+        self.pipeline.phase_timer()
+    }
+
+    /// Padding applied to the plaintext, if the mode of operation needs one
+    fn padding(&self) -> Option<&'static dyn Padding> {
+        let config = &self.config;
+        config
+            .mode
+            .needs_padding()
+            .then(|| config.padding.padding())
     }
 
-    pub fn cipher<R, W>(&mut self, input: R, output: W) -> std::io::Result<()>
+    /// Cipher the input. The output starts with a container header describing the key size,
+    /// mode, padding and IV, so that it can be deciphered without knowing those settings.
+    pub fn cipher<R, W>(&mut self, input: R, output: W) -> Result<()>
     where
-        R: std::io::Read,
-        W: std::io::Write,
+        R: Read + Send,
+        W: Write + Send,
     {
-        let mut chunk_reader = ChunkReader::new(input, 4 * N_B, true);
-        let mut chunk_writer = ChunkWriter::new(output, false);
+        let header = self.new_header();
+        let mut chunk_reader = ChunkReader::new(input, 4 * N_B, self.padding());
+        let mut chunk_writer = ChunkWriter::new(output, None);
+        chunk_writer.write_bytes(&header.to_bytes())?;
 
-        loop {
-            let chunks_filled = chunk_reader.read_chunks(BUFFER_SIZE, &mut self.buffer)?;
-
-            if chunks_filled == 0 {
-                break;
+        match self.config.mode {
+            Mode::Ecb => self.cipher_ecb(&mut chunk_reader, &mut chunk_writer),
+            Mode::Cbc => {
+                let iv = Self::iv_block(&header)?;
+                self.cipher_cbc(iv, &mut chunk_reader, &mut chunk_writer)
+            }
+            Mode::Ctr(layout) => {
+                let initial_counter = Self::iv_block(&header)?;
+                self.apply_ctr_keystream(
+                    layout,
+                    &initial_counter,
+                    0,
+                    &mut chunk_reader,
+                    &mut chunk_writer,
+                )
             }
+            Mode::Gcm => self.cipher_gcm(&header, &mut chunk_reader, &mut chunk_writer),
+        }?;
 
-            let ciphered_chunks = self.cipher_blocks(&self.buffer[..chunks_filled]);
+        self.phase_timer()
+            .measure(Phase::Write, || chunk_writer.finish())
+    }
 
-            chunk_writer.write_chunks(&ciphered_chunks)?;
-        }
+    /// Decipher the input. The mode, padding and IV are read from the container header, so
+    /// they may differ from the ones this cipher was created with, but the key size has to
+    /// match. Files ciphered in GCM mode are deciphered by `decipher_seekable` instead, since
+    /// the whole ciphertext is authenticated before any plaintext is written to the output.
+    pub fn decipher<R, W>(&mut self, mut input: R, output: W) -> Result<()>
+    where
+        R: Read + Send,
+        W: Write + Send,
+    {
+        let header = self.read_header(&mut input)?;
+        self.decipher_streamed(&header, input, output)
+    }
 
-        Ok(())
+    /// Decipher the input like `decipher`, including files ciphered in GCM mode, whose
+    /// ciphertext is read twice: once to check the authentication tag, and once to decipher it
+    pub fn decipher_seekable<R, W>(&mut self, mut input: R, output: W) -> Result<()>
+    where
+        R: Read + Seek + Send,
+        W: Write + Send,
+    {
+        let header = self.read_header(&mut input)?;
+        match header.mode {
+            Mode::Gcm => self.decipher_gcm(&header, input, output),
+            _ => self.decipher_streamed(&header, input, output),
+        }
     }
 
-    pub fn decipher<R, W>(&mut self, input: R, output: W) -> std::io::Result<()>
+    /// Decipher the text following the header in a single pass
+    fn decipher_streamed<R, W>(&mut self, header: &Header, input: R, output: W) -> Result<()>
     where
-        R: std::io::Read,
-        W: std::io::Write,
+        R: Read + Send,
+        W: Write + Send,
     {
-        let mut chunk_reader = ChunkReader::new(input, 4 * N_B, false);
-        let mut chunk_writer = ChunkWriter::new(output, true);
+        if header.mode == Mode::Gcm {
+            return Err(Error::Unsupported(
+                "deciphering a GCM file requires a seekable input, so that it is authenticated \
+                 before any plaintext is written"
+                    .to_string(),
+            ));
+        }
 
-        loop {
-            let chunks_filled = chunk_reader.read_chunks(BUFFER_SIZE, &mut self.buffer)?;
+        let padding = header
+            .mode
+            .needs_padding()
+            .then(|| header.padding.padding());
+        let mut chunk_reader = ChunkReader::new(input, 4 * N_B, None);
+        let mut chunk_writer = ChunkWriter::new(output, padding);
 
-            if chunks_filled == 0 {
-                break;
+        match header.mode {
+            Mode::Ecb => self.decipher_ecb(&mut chunk_reader, &mut chunk_writer),
+            Mode::Cbc => {
+                let iv = Self::iv_block(header)?;
+                self.decipher_cbc(iv, &mut chunk_reader, &mut chunk_writer)
+            }
+            Mode::Ctr(layout) => {
+                let initial_counter = Self::iv_block(header)?;
+                self.apply_ctr_keystream(
+                    layout,
+                    &initial_counter,
+                    0,
+                    &mut chunk_reader,
+                    &mut chunk_writer,
+                )
             }
+            Mode::Gcm => unreachable!("GCM is deciphered in two passes"),
+        }?;
+
+        self.phase_timer()
+            .measure(Phase::Write, || chunk_writer.finish())
+    }
 
-            let deciphered_chunks = self.decipher_blocks(&self.buffer[..chunks_filled]);
+    /// Decipher `len` bytes of the plaintext starting at byte `start`, without processing the
+    /// ciphertext before it. Only supported for files ciphered in CTR mode, where every block
+    /// can be deciphered on its own. The range is truncated if it goes past the end of the
+    /// input, and rejected if it goes past the blocks the counter can take.
+    pub fn decipher_range<R, W>(
+        &mut self,
+        mut input: R,
+        output: W,
+        start: u64,
+        len: u64,
+    ) -> Result<()>
+    where
+        R: Read + Seek + Send,
+        W: Write + Send,
+    {
+        let header = self.read_header(&mut input)?;
+        let Mode::Ctr(layout) = header.mode else {
+            return Err(Error::Unsupported(
+                "deciphering a byte range is only supported in CTR mode".to_string(),
+            ));
+        };
+
+        let initial_counter = Self::iv_block(&header)?;
+        let text_start = input.stream_position()?;
+        let text_len = input.seek(SeekFrom::End(0))? - text_start;
+        let len = len.min(text_len.saturating_sub(start));
+        layout.check_range(start, len)?;
+        input.seek(SeekFrom::Start(text_start + start))?;
+
+        let mut chunk_reader = ChunkReader::new(input.take(len), 4 * N_B, None);
+        let mut chunk_writer = ChunkWriter::new(output, None);
+
+        self.apply_ctr_keystream(
+            layout,
+            &initial_counter,
+            start,
+            &mut chunk_reader,
+            &mut chunk_writer,
+        )?;
+
+        self.phase_timer()
+            .measure(Phase::Write, || chunk_writer.finish())
+    }
 
-            chunk_writer.write_chunks(&deciphered_chunks)?;
+    /// Header of a file ciphered with the settings of this cipher and a new random IV
+    fn new_header(&self) -> Header {
+        Header::new(
+            self.block_cipher.key_size(),
+            self.config.mode,
+            self.config.padding,
+            self.config.mode.random_iv(),
+            self.derived_key
+                .as_ref()
+                .map(|derived_key| derived_key.kdf.clone()),
+        )
+    }
+
+    /// Read the container header and check that it can be deciphered with this cipher. If the
+    /// file was ciphered with a passphrase and other KDF parameters, the key is derived again
+    /// and kept for the following files.
+    fn read_header<R: Read>(&mut self, input: &mut R) -> Result<Header> {
+        let header = Header::read_from(input)?;
+
+        match (&header.kdf, &mut self.derived_key) {
+            (None, None) => {
+                let key_size = self.block_cipher.key_size();
+                if header.key_size != key_size {
+                    return Err(Error::KeyMismatch(format!(
+                        "the file was ciphered with a {} bit key, but the key is {} bits long",
+                        8 * header.key_size.key_len(),
+                        8 * key_size.key_len()
+                    )));
+                }
+            }
+            (Some(kdf), Some(derived_key)) => {
+                if *kdf != derived_key.kdf || header.key_size != self.block_cipher.key_size() {
+                    let key = kdf.derive_key(&derived_key.passphrase, header.key_size);
+                    self.block_cipher = self.config.implementation.new_block_cipher(&key)?;
+                    derived_key.kdf = kdf.clone();
+                }
+            }
+            (Some(_), None) => {
+                return Err(Error::KeyMismatch(
+                    "the file was ciphered with a passphrase, but a key was given".to_string(),
+                ));
+            }
+            (None, Some(_)) => {
+                return Err(Error::KeyMismatch(
+                    "the file was ciphered with a key, but a passphrase was given".to_string(),
+                ));
+            }
         }
 
-        Ok(())
+        Ok(header)
+    }
+
+    /// The IV of the modes that use a whole block, which the header has already validated
+    fn iv_block(header: &Header) -> Result<[u8; 4 * N_B]> {
+        header
+            .iv
+            .as_slice()
+            .try_into()
+            .map_err(|_| Error::InvalidHeader("the IV is not a whole block".to_string()))
     }
 
-    pub fn cipher_file(
+    fn cipher_ecb<R: Read + Send, W: Write + Send>(
         &mut self,
-        input_file: &str,
-        output_file: &str,
-    ) -> Result<(), std::io::Error> {
-        let input = std::fs::File::open(input_file)?;
-        let output = std::fs::File::create(output_file)?;
+        chunk_reader: &mut ChunkReader<R>,
+        chunk_writer: &mut ChunkWriter<W>,
+    ) -> Result<()> {
+        let block_cipher = &*self.block_cipher;
+        self.pipeline
+            .run(chunk_reader, chunk_writer, Output::Blocks, |batch| {
+                self.executor.for_each_block_chunk(
+                    batch.blocks_mut(),
+                    block_cipher.parallel_blocks(),
+                    |_, blocks| block_cipher.cipher_blocks(blocks),
+                );
+                Ok(())
+            })
+    }
+
+    fn decipher_ecb<R: Read + Send, W: Write + Send>(
+        &mut self,
+        chunk_reader: &mut ChunkReader<R>,
+        chunk_writer: &mut ChunkWriter<W>,
+    ) -> Result<()> {
+        let block_cipher = &*self.block_cipher;
+        self.pipeline
+            .run(chunk_reader, chunk_writer, Output::Blocks, |batch| {
+                self.executor.for_each_block_chunk(
+                    batch.blocks_mut(),
+                    block_cipher.parallel_blocks(),
+                    |_, blocks| block_cipher.inv_cipher_blocks(blocks),
+                );
+                Ok(())
+            })
+    }
+
+    /// CBC encryption is inherently sequential, so the blocks are chained on the current thread
+    /// while the input is streamed in batches.
+    fn cipher_cbc<R: Read + Send, W: Write + Send>(
+        &mut self,
+        mut iv: [u8; 4 * N_B],
+        chunk_reader: &mut ChunkReader<R>,
+        chunk_writer: &mut ChunkWriter<W>,
+    ) -> Result<()> {
+        self.pipeline
+            .run(chunk_reader, chunk_writer, Output::Blocks, |batch| {
+                cbc::cipher_blocks(&*self.block_cipher, &mut iv, batch.blocks_mut());
+                Ok(())
+            })
+    }
+
+    fn decipher_cbc<R: Read + Send, W: Write + Send>(
+        &mut self,
+        mut iv: [u8; 4 * N_B],
+        chunk_reader: &mut ChunkReader<R>,
+        chunk_writer: &mut ChunkWriter<W>,
+    ) -> Result<()> {
+        self.pipeline
+            .run(chunk_reader, chunk_writer, Output::Blocks, |batch| {
+                cbc::decipher_blocks(
+                    &*self.block_cipher,
+                    &*self.executor,
+                    &mut iv,
+                    batch.blocks_mut(),
+                    &mut self.ciphertext_buffer,
+                );
+                Ok(())
+            })
+    }
+
+    /// CTR encryption and decryption are the same operation: the keystream for each batch is
+    /// generated in parallel and XORed with the data. The last block may be partial, since
+    /// there is no padding. Fails at the first batch that would wrap the counter around.
+    fn apply_ctr_keystream<R: Read + Send, W: Write + Send>(
+        &mut self,
+        layout: CounterLayout,
+        initial_counter: &[u8; 4 * N_B],
+        offset: u64,
+        chunk_reader: &mut ChunkReader<R>,
+        chunk_writer: &mut ChunkWriter<W>,
+    ) -> Result<()> {
+        let mut offset = offset;
+        self.pipeline
+            .run(chunk_reader, chunk_writer, Output::Bytes, |batch| {
+                let data = batch.bytes_mut();
+                ctr::apply_keystream(
+                    &*self.block_cipher,
+                    &*self.executor,
+                    initial_counter,
+                    layout,
+                    offset,
+                    data,
+                )?;
+                offset += data.len() as u64;
+                Ok(())
+            })
+    }
+
+    /// The ciphertext is generated in parallel as in CTR mode, and then authenticated together
+    /// with the header. The authentication tag is written after the ciphertext.
+    fn cipher_gcm<R: Read + Send, W: Write + Send>(
+        &mut self,
+        header: &Header,
+        chunk_reader: &mut ChunkReader<R>,
+        chunk_writer: &mut ChunkWriter<W>,
+    ) -> Result<()> {
+        let mut gcm_state = GcmState::new(&*self.block_cipher, &header.iv, &header.to_bytes())?;
+        let initial_counter = gcm_state.initial_counter();
+        let mut offset = 0;
+
+        self.pipeline
+            .run(chunk_reader, chunk_writer, Output::Bytes, |batch| {
+                let data = batch.bytes_mut();
+                gcm::check_text_len(offset + data.len() as u64)?;
+                ctr::apply_keystream(
+                    &*self.block_cipher,
+                    &*self.executor,
+                    &initial_counter,
+                    CounterLayout::Ctr32,
+                    offset,
+                    data,
+                )?;
+                gcm_state.update(data);
+                offset += data.len() as u64;
+                Ok(())
+            })?;
+
+        Ok(chunk_writer.write_bytes(&gcm_state.finalize(&*self.block_cipher))?)
+    }
+
+    /// The input is read twice: first to check the authentication tag, and then, only if it
+    /// matches, to decipher it. This way no unauthenticated plaintext is ever released.
+    fn decipher_gcm<R: Read + Seek + Send, W: Write + Send>(
+        &mut self,
+        header: &Header,
+        mut input: R,
+        output: W,
+    ) -> Result<()> {
+        let text_start = input.stream_position()?;
+        let text_len = input
+            .seek(SeekFrom::End(0))?
+            .checked_sub(text_start + header.tag_len as u64)
+            .ok_or(Error::InvalidHeader(
+                "the file is too short to contain an authentication tag".to_string(),
+            ))?;
+        gcm::check_text_len(text_len)?;
+
+        let mut tag = vec![0; header.tag_len];
+        input.seek(SeekFrom::Start(text_start + text_len))?;
+        input.read_exact(&mut tag)?;
+
+        let mut gcm_state = GcmState::new(&*self.block_cipher, &header.iv, &header.to_bytes())?;
+        let initial_counter = gcm_state.initial_counter();
+
+        input.seek(SeekFrom::Start(text_start))?;
+        let mut chunk_reader = ChunkReader::new(input.by_ref().take(text_len), 4 * N_B, None);
+        // Nothing is written in the first pass, which only authenticates the ciphertext
+        let mut discarding_writer = ChunkWriter::new(std::io::sink(), None);
+        self.pipeline.run(
+            &mut chunk_reader,
+            &mut discarding_writer,
+            Output::Bytes,
+            |batch| {
+                gcm_state.update(batch.bytes());
+                Ok(())
+            },
+        )?;
+
+        if !gcm::tags_match(&gcm_state.finalize(&*self.block_cipher), &tag) {
+            return Err(Error::AuthenticationFailed);
+        }
+
+        input.seek(SeekFrom::Start(text_start))?;
+        let mut chunk_reader = ChunkReader::new(input.take(text_len), 4 * N_B, None);
+        let mut chunk_writer = ChunkWriter::new(output, None);
+        self.apply_ctr_keystream(
+            CounterLayout::Ctr32,
+            &initial_counter,
+            0,
+            &mut chunk_reader,
+            &mut chunk_writer,
+        )?;
+
+        self.phase_timer()
+            .measure(Phase::Write, || chunk_writer.finish())
+    }
+
+    /// Cipher the input file into the output file. With `AESCipherConfig::mmap`, both files are
+    /// mapped into memory if they are regular files, and with `AESCipherConfig::positional_io`
+    /// they are read and written in parallel in the modes that allow it. Otherwise they are
+    /// streamed.
+    pub fn cipher_file(&mut self, input_file: &str, output_file: &str) -> Result<()> {
+        if self.config.mmap && regular_file::are_regular(input_file, output_file) {
+            return self.cipher_mapped_file(input_file, output_file);
+        }
+        #[cfg(unix)]
+        if self.config.positional_io
+            && self.config.mode.has_independent_blocks()
+            && regular_file::are_regular(input_file, output_file)
+        {
+            return self.cipher_positional_file(input_file, output_file);
+        }
+        let input = File::open(input_file)?;
+        let output = File::create(output_file)?;
         self.cipher(input, output)
     }
 
-    pub fn decipher_file(
+    /// Decipher the input file into the output file, mapping them into memory or reading and
+    /// writing them in parallel as in `cipher_file`
+    pub fn decipher_file(&mut self, input_file: &str, output_file: &str) -> Result<()> {
+        if self.config.mmap && regular_file::are_regular(input_file, output_file) {
+            return self.decipher_mapped_file(input_file, output_file);
+        }
+        #[cfg(unix)]
+        if self.config.positional_io && regular_file::are_regular(input_file, output_file) {
+            // Invalid headers are reported by the streaming path
+            let header = Header::read_from(&mut File::open(input_file)?);
+            if header.is_ok_and(|header| header.mode.has_independent_blocks()) {
+                return self.decipher_positional_file(input_file, output_file);
+            }
+        }
+        let input = File::open(input_file)?;
+        let output = File::create(output_file)?;
+        self.decipher_seekable(input, output)
+    }
+
+    pub fn decipher_file_range(
         &mut self,
         input_file: &str,
         output_file: &str,
-    ) -> Result<(), std::io::Error> {
-        let input = std::fs::File::open(input_file)?;
-        let output = std::fs::File::create(output_file)?;
-        self.decipher(input, output)
+        start: u64,
+        len: u64,
+    ) -> Result<()> {
+        let input = File::open(input_file)?;
+        let output = File::create(output_file)?;
+        self.decipher_range(input, output, start, len)
+    }
+
+    /// The output file is sized upfront to hold the header, the padded ciphertext and the tag.
+    /// The plaintext is copied into it and ciphered in place, with the executor working on
+    /// disjoint parts of the map in the modes that can be parallelised.
+    fn cipher_mapped_file(&mut self, input_file: &str, output_file: &str) -> Result<()> {
+        let input = regular_file::map_input(input_file)?;
+        let header = self.new_header();
+        let header_bytes = header.to_bytes();
+
+        let full_len = input.len() - input.len() % (4 * N_B);
+        let mut last_block = [0; 4 * N_B];
+        let mut text_len = input.len();
+        if let Some(padding) = self.padding() {
+            let last_len = input.len() - full_len;
+            last_block[..last_len].copy_from_slice(&input[full_len..]);
+            let has_last_block = padding
+                .pad(&mut last_block, last_len)
+                .map_err(Error::InvalidPadding)?;
+            text_len = full_len + if has_last_block { 4 * N_B } else { 0 };
+        }
+
+        // Checked before the plaintext is copied into the output
+        match self.config.mode {
+            Mode::Ctr(layout) => layout.check_range(0, text_len as u64)?,
+            Mode::Gcm => gcm::check_text_len(text_len as u64)?,
+            Mode::Ecb | Mode::Cbc => {}
+        }
+        let output_len = header_bytes.len() + text_len + header.tag_len;
+        let (_, mut output) = regular_file::map_output(output_file, output_len as u64)?;
+        let (header_part, rest) = output.split_at_mut(header_bytes.len());
+        let (text, tag) = rest.split_at_mut(text_len);
+        header_part.copy_from_slice(&header_bytes);
+        let start_time = Instant::now();
+        match self.padding() {
+            Some(_) => {
+                text[..full_len].copy_from_slice(&input[..full_len]);
+                text[full_len..].copy_from_slice(&last_block[..text_len - full_len]);
+            }
+            None => text.copy_from_slice(&input),
+        }
+        let start_time = self.phase_timer().add_since(Phase::Read, start_time);
+
+        let block_cipher = &*self.block_cipher;
+        match self.config.mode {
+            Mode::Ecb => self.executor.for_each_block_chunk(
+                text.as_chunks_mut().0,
+                block_cipher.parallel_blocks(),
+                |_, blocks| block_cipher.cipher_blocks(blocks),
+            ),
+            Mode::Cbc => {
+                let mut iv = Self::iv_block(&header)?;
+                cbc::cipher_blocks(block_cipher, &mut iv, text.as_chunks_mut().0);
+            }
+            Mode::Ctr(layout) => {
+                let initial_counter = Self::iv_block(&header)?;
+                ctr::apply_keystream(
+                    block_cipher,
+                    &*self.executor,
+                    &initial_counter,
+                    layout,
+                    0,
+                    text,
+                )?;
+            }
+            Mode::Gcm => {
+                let mut gcm_state = GcmState::new(block_cipher, &header.iv, &header_bytes)?;
+                ctr::apply_keystream(
+                    block_cipher,
+                    &*self.executor,
+                    &gcm_state.initial_counter(),
+                    CounterLayout::Ctr32,
+                    0,
+                    text,
+                )?;
+                gcm_state.update(text);
+                tag.copy_from_slice(&gcm_state.finalize(block_cipher));
+            }
+        }
+        self.phase_timer().add_since(Phase::Cipher, start_time);
+        Ok(())
+    }
+
+    /// The ciphertext is copied from the input map into the output and deciphered there. In GCM
+    /// mode it is authenticated before the output file is created, and with padding the output
+    /// file is truncated once the padding of the last block has been checked.
+    fn decipher_mapped_file(&mut self, input_file: &str, output_file: &str) -> Result<()> {
+        let input = regular_file::map_input(input_file)?;
+        let mut text: &[u8] = &input;
+        let header = self.read_header(&mut text)?;
+
+        let padding = header
+            .mode
+            .needs_padding()
+            .then(|| header.padding.padding());
+        if padding.is_some() && !text.len().is_multiple_of(4 * N_B) {
+            return Err(Error::InvalidPadding(
+                "the ciphertext is not a whole number of blocks".to_string(),
+            ));
+        }
+
+        let mut initial_counter = [0; 4 * N_B];
+        if header.mode == Mode::Gcm {
+            let text_len = text
+                .len()
+                .checked_sub(header.tag_len)
+                .ok_or(Error::InvalidHeader(
+                    "the file is too short to contain an authentication tag".to_string(),
+                ))?;
+            gcm::check_text_len(text_len as u64)?;
+            let tag;
+            (text, tag) = text.split_at(text_len);
+
+            let mut gcm_state = GcmState::new(&*self.block_cipher, &header.iv, &header.to_bytes())?;
+            initial_counter = gcm_state.initial_counter();
+            self.phase_timer()
+                .measure(Phase::Cipher, || gcm_state.update(text));
+            if !gcm::tags_match(&gcm_state.finalize(&*self.block_cipher), tag) {
+                return Err(Error::AuthenticationFailed);
+            }
+        }
+
+        // Checked before the output file is created
+        if let Mode::Ctr(layout) = header.mode {
+            layout.check_range(0, text.len() as u64)?;
+        }
+        let (file, mut output) = regular_file::map_output(output_file, text.len() as u64)?;
+        let start_time = Instant::now();
+        output.copy_from_slice(text);
+        let start_time = self.phase_timer().add_since(Phase::Read, start_time);
+
+        let block_cipher = &*self.block_cipher;
+        match header.mode {
+            Mode::Ecb => self.executor.for_each_block_chunk(
+                output.as_chunks_mut().0,
+                block_cipher.parallel_blocks(),
+                |_, blocks| block_cipher.inv_cipher_blocks(blocks),
+            ),
+            Mode::Cbc => {
+                let mut iv = Self::iv_block(&header)?;
+                cbc::decipher_copied_blocks(
+                    block_cipher,
+                    &*self.executor,
+                    &mut iv,
+                    text.as_chunks().0,
+                    output.as_chunks_mut().0,
+                );
+            }
+            Mode::Ctr(layout) => {
+                let initial_counter = Self::iv_block(&header)?;
+                ctr::apply_keystream(
+                    block_cipher,
+                    &*self.executor,
+                    &initial_counter,
+                    layout,
+                    0,
+                    &mut output,
+                )?;
+            }
+            Mode::Gcm => ctr::apply_keystream(
+                block_cipher,
+                &*self.executor,
+                &initial_counter,
+                CounterLayout::Ctr32,
+                0,
+                &mut output,
+            )?,
+        }
+        self.phase_timer().add_since(Phase::Cipher, start_time);
+
+        if let Some(padding) = padding {
+            let last_block = output.as_chunks().0.last();
+            let padding_len =
+                last_block.map_or(0, |_| 4 * N_B) - unpadded_len(padding, last_block)?;
+            drop(output);
+            file.set_len((text.len() - padding_len) as u64)?;
+        }
+        Ok(())
+    }
+
+    /// Each part of the input is read, ciphered and written by a single task, with the blocks
+    /// of the part ciphered on the thread of the task. The padded last block is read upfront,
+    /// so that the size of the output is known.
+    #[cfg(unix)]
+    fn cipher_positional_file(&mut self, input_file: &str, output_file: &str) -> Result<()> {
+        let input = File::open(input_file)?;
+        let input_len = input.metadata()?.len();
+        let header = self.new_header();
+        let header_bytes = header.to_bytes();
+
+        let full_len = input_len - input_len % (4 * N_B) as u64;
+        let mut last_block = [0; 4 * N_B];
+        let mut text_len = input_len;
+        if let Some(padding) = self.padding() {
+            let last_len = (input_len - full_len) as usize;
+            input.read_exact_at(&mut last_block[..last_len], full_len)?;
+            let has_last_block = padding
+                .pad(&mut last_block, last_len)
+                .map_err(Error::InvalidPadding)?;
+            text_len = full_len + if has_last_block { 4 * N_B as u64 } else { 0 };
+        }
+
+        if let Mode::Ctr(layout) = self.config.mode {
+            layout.check_range(0, text_len)?;
+        }
+        let text_start = header_bytes.len() as u64;
+        let output = regular_file::create_output(output_file, text_start + text_len)?;
+        output.write_all_at(&header_bytes, 0)?;
+
+        let block_cipher = &*self.block_cipher;
+        let mode = self.config.mode;
+        let initial_counter = match mode {
+            Mode::Ctr(_) => Self::iv_block(&header)?,
+            _ => [0; 4 * N_B],
+        };
+        let phase_timer = self.phase_timer();
+        self.for_each_file_part(text_len, |offset, part| {
+            let start_time = Instant::now();
+            let read_len = part.len().min((input_len - offset) as usize);
+            input.read_exact_at(&mut part[..read_len], offset)?;
+            // Only the part with the padded last block goes past the end of the input
+            if offset + part.len() as u64 > input_len {
+                let last_block_start = (full_len - offset) as usize;
+                part[last_block_start..].copy_from_slice(&last_block);
+            }
+            let start_time = phase_timer.add_since(Phase::Read, start_time);
+
+            match mode {
+                Mode::Ecb => block_cipher.cipher_blocks(part.as_chunks_mut().0),
+                Mode::Ctr(layout) => ctr::apply_keystream(
+                    block_cipher,
+                    &concurrency::SequentialExecutor,
+                    &initial_counter,
+                    layout,
+                    offset,
+                    part,
+                )?,
+                Mode::Cbc | Mode::Gcm => unreachable!("{:?} blocks depend on each other", mode),
+            }
+            phase_timer.add_since(Phase::Cipher, start_time);
+            phase_timer.measure(Phase::Write, || {
+                output.write_all_at(part, text_start + offset)
+            })?;
+            Ok(())
+        })
+    }
+
+    /// Like `cipher_positional_file`, with the padding removed by truncating the output once
+    /// every part has been written
+    #[cfg(unix)]
+    fn decipher_positional_file(&mut self, input_file: &str, output_file: &str) -> Result<()> {
+        let mut input = File::open(input_file)?;
+        let header = self.read_header(&mut input)?;
+        let text_start = input.stream_position()?;
+        let text_len = input.metadata()?.len() - text_start;
+
+        let padding = header
+            .mode
+            .needs_padding()
+            .then(|| header.padding.padding());
+        if padding.is_some() && !text_len.is_multiple_of(4 * N_B as u64) {
+            return Err(Error::InvalidPadding(
+                "the ciphertext is not a whole number of blocks".to_string(),
+            ));
+        }
+
+        if let Mode::Ctr(layout) = header.mode {
+            layout.check_range(0, text_len)?;
+        }
+        let output = regular_file::create_output(output_file, text_len)?;
+        let block_cipher = &*self.block_cipher;
+        let initial_counter = match header.mode {
+            Mode::Ctr(_) => Self::iv_block(&header)?,
+            _ => [0; 4 * N_B],
+        };
+        let phase_timer = self.phase_timer();
+        self.for_each_file_part(text_len, |offset, part| {
+            let start_time = Instant::now();
+            input.read_exact_at(part, text_start + offset)?;
+            let start_time = phase_timer.add_since(Phase::Read, start_time);
+            match header.mode {
+                Mode::Ecb => block_cipher.inv_cipher_blocks(part.as_chunks_mut().0),
+                Mode::Ctr(layout) => ctr::apply_keystream(
+                    block_cipher,
+                    &concurrency::SequentialExecutor,
+                    &initial_counter,
+                    layout,
+                    offset,
+                    part,
+                )?,
+                Mode::Cbc | Mode::Gcm => {
+                    unreachable!("{:?} blocks depend on each other", header.mode)
+                }
+            }
+            phase_timer.add_since(Phase::Cipher, start_time);
+            phase_timer.measure(Phase::Write, || output.write_all_at(part, offset))?;
+            Ok(())
+        })?;
+
+        if let Some(padding) = padding {
+            let mut last_block = [0; 4 * N_B];
+            let last_block = match text_len.checked_sub(4 * N_B as u64) {
+                Some(last_block_start) => {
+                    output.read_exact_at(&mut last_block, last_block_start)?;
+                    Some(&last_block)
+                }
+                None => None,
+            };
+            let padding_len =
+                last_block.map_or(0, |_| 4 * N_B) - unpadded_len(padding, last_block)?;
+            output.set_len(text_len - padding_len as u64)?;
+        }
+        Ok(())
+    }
+
+    /// Split the `len` bytes of a file into parts of `buffer_blocks` blocks, and call `f` with
+    /// the offset of each part and a buffer of its length. The executor runs one part per
+    /// thread at a time, so only that many buffers are allocated. Stops at the first error.
+    #[cfg(unix)]
+    fn for_each_file_part<F>(&self, len: u64, f: F) -> Result<()>
+    where
+        F: Fn(u64, &mut [u8]) -> Result<()> + Sync,
+    {
+        let part_len = 4 * N_B * self.config.buffer_blocks;
+        let mut buffer = vec![0; part_len * self.config.n_threads];
+        let error = std::sync::Mutex::new(None);
+
+        for round_start in (0..len).step_by(buffer.len()) {
+            let round_len = (len - round_start).min(buffer.len() as u64) as usize;
+            self.executor
+                .for_each_chunk(&mut buffer[..round_len], part_len, &|i, part| {
+                    let offset = round_start + (i * part_len) as u64;
+                    if let Err(e) = f(offset, part) {
+                        error.lock().unwrap().get_or_insert(e);
+                    }
+                });
+            if let Some(e) = error.lock().unwrap().take() {
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    const CIPHER_KEY: [u8; 16] = [
+        0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f,
+        0x3c,
+    ];
+
+    fn config(mode: Mode, padding: PaddingScheme) -> AESCipherConfig {
+        AESCipherConfig {
+            n_threads: 2,
+            mode,
+            padding,
+            ..Default::default()
+        }
+    }
+
+    fn round_trip(mode: Mode, plain_text: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut cipher = AESCipher::new(&CIPHER_KEY, config(mode, PaddingScheme::Pkcs7)).unwrap();
+
+        let mut cipher_text = Vec::new();
+        cipher.cipher(plain_text, &mut cipher_text).unwrap();
+
+        let mut deciphered_text = Vec::new();
+        cipher
+            .decipher_seekable(Cursor::new(&cipher_text), &mut deciphered_text)
+            .unwrap();
+
+        (cipher_text, deciphered_text)
+    }
+
+    fn header_len(mode: Mode) -> usize {
+        Header::new(
+            KeySize::Aes128,
+            mode,
+            PaddingScheme::Pkcs7,
+            mode.random_iv(),
+            None,
+        )
+        .to_bytes()
+        .len()
+    }
+
+    #[test]
+    fn test_ecb_round_trip_with_zero_bytes() {
+        // Binary data ending with zeros, which null padding used to strip
+        let plain_text: Vec<u8> = (0..10000u32).map(|i| (i % 7) as u8).collect();
+
+        let (cipher_text, deciphered_text) = round_trip(Mode::Ecb, &plain_text);
+
+        assert_eq!(cipher_text.len(), header_len(Mode::Ecb) + 10000 + 4 * N_B);
+        assert_eq!(deciphered_text, plain_text);
+    }
+
+    #[test]
+    fn test_decipher_with_wrong_key_fails_on_padding() {
+        let plain_text = "Hello World! ".repeat(10).into_bytes();
+        let (cipher_text, _) = round_trip(Mode::Cbc, &plain_text);
+
+        let mut other_key = CIPHER_KEY;
+        other_key[0] ^= 1;
+        let mut cipher =
+            AESCipher::new(&other_key, config(Mode::Cbc, PaddingScheme::Pkcs7)).unwrap();
+
+        let mut deciphered_text = Vec::new();
+        let result = cipher.decipher(Cursor::new(&cipher_text), &mut deciphered_text);
+
+        assert!(matches!(result, Err(Error::InvalidPadding(_))));
+    }
+
+    #[test]
+    fn test_cbc_round_trip() {
+        let plain_text = "Hello World! ".repeat(20000).into_bytes();
+
+        let (cipher_text, deciphered_text) = round_trip(Mode::Cbc, &plain_text);
+
+        assert_eq!(
+            cipher_text.len(),
+            header_len(Mode::Cbc) + (plain_text.len() / (4 * N_B) + 1) * 4 * N_B
+        );
+        assert_eq!(deciphered_text, plain_text);
+    }
+
+    #[test]
+    fn test_ctr_round_trip_without_padding() {
+        let plain_text = "Hello World! ".repeat(20000).into_bytes();
+
+        for layout in [CounterLayout::Ctr64, CounterLayout::Ctr128] {
+            let (cipher_text, deciphered_text) = round_trip(Mode::Ctr(layout), &plain_text);
+
+            assert_eq!(
+                cipher_text.len(),
+                header_len(Mode::Ctr(layout)) + plain_text.len()
+            );
+            assert_eq!(deciphered_text, plain_text);
+        }
+    }
+
+    #[test]
+    fn test_ctr_decipher_range() {
+        let plain_text = "Hello World! ".repeat(100).into_bytes();
+        let mut cipher = AESCipher::new(
+            &CIPHER_KEY,
+            config(Mode::Ctr(CounterLayout::Ctr64), PaddingScheme::Pkcs7),
+        )
+        .unwrap();
+        let mut cipher_text = Vec::new();
+        cipher
+            .cipher(plain_text.as_slice(), &mut cipher_text)
+            .unwrap();
+
+        for (start, len) in [(0, 10), (13, 13), (100, 1000), (1299, 10)] {
+            let mut deciphered_text = Vec::new();
+            cipher
+                .decipher_range(
+                    Cursor::new(&cipher_text),
+                    &mut deciphered_text,
+                    start as u64,
+                    len as u64,
+                )
+                .unwrap();
+
+            let end = (start + len).min(plain_text.len());
+            assert_eq!(deciphered_text, plain_text[start..end]);
+        }
+    }
+
+    #[test]
+    fn test_gcm_round_trip() {
+        let plain_text = "Hello World! ".repeat(20000).into_bytes();
+
+        let (cipher_text, deciphered_text) = round_trip(Mode::Gcm, &plain_text);
+
+        assert_eq!(
+            cipher_text.len(),
+            header_len(Mode::Gcm) + plain_text.len() + gcm::TAG_LEN
+        );
+        assert_eq!(deciphered_text, plain_text);
+    }
+
+    #[test]
+    fn test_gcm_detects_tampering() {
+        let plain_text = "Hello World! ".repeat(100).into_bytes();
+        let mut cipher =
+            AESCipher::new(&CIPHER_KEY, config(Mode::Gcm, PaddingScheme::Pkcs7)).unwrap();
+        let mut cipher_text = Vec::new();
+        cipher
+            .cipher(plain_text.as_slice(), &mut cipher_text)
+            .unwrap();
+
+        // The padding id is not used by GCM, but it is authenticated with the rest of the header
+        let padding_id_position = 7;
+        let iv_position = 9;
+        let positions = [
+            padding_id_position,
+            iv_position,
+            header_len(Mode::Gcm) + 500,
+            cipher_text.len() - 1,
+        ];
+        for position in positions {
+            let mut modified_cipher_text = cipher_text.clone();
+            modified_cipher_text[position] ^= 0x01;
+
+            let mut deciphered_text = Vec::new();
+            let result =
+                cipher.decipher_seekable(Cursor::new(&modified_cipher_text), &mut deciphered_text);
+
+            assert!(matches!(result, Err(Error::AuthenticationFailed)));
+            assert!(deciphered_text.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_cbc_hides_repeated_blocks() {
+        let plain_text = [0x41u8; 4 * 4 * N_B];
+
+        let (cipher_text, _) = round_trip(Mode::Cbc, &plain_text);
+
+        let blocks: Vec<_> = cipher_text[header_len(Mode::Cbc)..]
+            .chunks(4 * N_B)
+            .collect();
+        assert!((1..blocks.len()).all(|i| blocks[i] != blocks[i - 1]));
+    }
+
+    #[test]
+    fn test_pipelined_matches_sequential() {
+        // Small batches, so that the pipeline goes through many of them
+        let plain_text = "Hello World! ".repeat(5000).into_bytes();
+        let modes = [
+            Mode::Ecb,
+            Mode::Cbc,
+            Mode::Ctr(CounterLayout::Ctr64),
+            Mode::Gcm,
+        ];
+
+        for mode in modes {
+            let cipher_config = |pipelined| AESCipherConfig {
+                buffer_blocks: 64,
+                pipelined,
+                ..config(mode, PaddingScheme::Pkcs7)
+            };
+            let mut sequential = AESCipher::new(&CIPHER_KEY, cipher_config(false)).unwrap();
+            let mut pipelined = AESCipher::new(&CIPHER_KEY, cipher_config(true)).unwrap();
+
+            let mut cipher_text = Vec::new();
+            pipelined
+                .cipher(plain_text.as_slice(), &mut cipher_text)
+                .unwrap();
+            let mut deciphered_text = Vec::new();
+            sequential
+                .decipher_seekable(Cursor::new(&cipher_text), &mut deciphered_text)
+                .unwrap();
+            assert_eq!(deciphered_text, plain_text, "{:?}", mode);
+
+            let mut cipher_text = Vec::new();
+            sequential
+                .cipher(plain_text.as_slice(), &mut cipher_text)
+                .unwrap();
+            let mut deciphered_text = Vec::new();
+            pipelined
+                .decipher_seekable(Cursor::new(&cipher_text), &mut deciphered_text)
+                .unwrap();
+            assert_eq!(deciphered_text, plain_text, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_with_every_backend() {
+        let plain_text = "Hello World! ".repeat(3000).into_bytes();
+        let backends = [
+            Backend::Rayon,
+            Backend::Scoped,
+            Backend::Queue,
+            Backend::Sequential,
+        ];
+        let modes = [
+            Mode::Ecb,
+            Mode::Cbc,
+            Mode::Ctr(CounterLayout::Ctr128),
+            Mode::Gcm,
+        ];
+
+        for mode in modes {
+            let mut reference =
+                AESCipher::new(&CIPHER_KEY, config(mode, PaddingScheme::Pkcs7)).unwrap();
+            let mut cipher_text = Vec::new();
+            reference
+                .cipher(plain_text.as_slice(), &mut cipher_text)
+                .unwrap();
+
+            for (backend, task_blocks) in backends.into_iter().flat_map(|backend| {
+                // A task can be larger than the whole batch
+                [1, 7, 1000].map(|task_blocks| (backend, task_blocks))
+            }) {
+                let cipher_config = AESCipherConfig {
+                    n_threads: 3,
+                    backend,
+                    task_blocks,
+                    buffer_blocks: 100,
+                    ..config(mode, PaddingScheme::Pkcs7)
+                };
+                let mut cipher = AESCipher::new(&CIPHER_KEY, cipher_config).unwrap();
+
+                let mut deciphered_text = Vec::new();
+                cipher
+                    .decipher_seekable(Cursor::new(&cipher_text), &mut deciphered_text)
+                    .unwrap();
+                assert_eq!(
+                    deciphered_text, plain_text,
+                    "{:?} {:?} {}",
+                    mode, backend, task_blocks
+                );
+
+                let mut own_cipher_text = Vec::new();
+                cipher
+                    .cipher(plain_text.as_slice(), &mut own_cipher_text)
+                    .unwrap();
+                let mut deciphered_text = Vec::new();
+                reference
+                    .decipher_seekable(Cursor::new(&own_cipher_text), &mut deciphered_text)
+                    .unwrap();
+                assert_eq!(
+                    deciphered_text, plain_text,
+                    "{:?} {:?} {}",
+                    mode, backend, task_blocks
+                );
+            }
+        }
+    }
+
+    /// Paths of a plaintext, ciphertext and deciphered file in a new temporary directory, which
+    /// is removed with them when it is dropped, even if the test fails
+    fn temp_files() -> (tempfile::TempDir, [String; 3]) {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = ["plain", "cipher", "deciphered"]
+            .map(|name| dir.path().join(name).to_string_lossy().into_owned());
+        (dir, paths)
+    }
+
+    #[test]
+    fn test_mmap_matches_streaming() {
+        let modes = [
+            Mode::Ecb,
+            Mode::Cbc,
+            Mode::Ctr(CounterLayout::Ctr32),
+            Mode::Gcm,
+        ];
+        let (_dir, [plain_file, cipher_file, deciphered_file]) = temp_files();
+
+        for (mode, len) in modes
+            .into_iter()
+            .flat_map(|mode| [1, 16, 1000, 4099].map(|len| (mode, len)))
+        {
+            let plain_text: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            std::fs::write(&plain_file, &plain_text).unwrap();
+            let cipher_config = |mmap| AESCipherConfig {
+                n_threads: 3,
+                mmap,
+                ..config(mode, PaddingScheme::Pkcs7)
+            };
+            let mut streaming = AESCipher::new(&CIPHER_KEY, cipher_config(false)).unwrap();
+            let mut mapped = AESCipher::new(&CIPHER_KEY, cipher_config(true)).unwrap();
+
+            mapped.cipher_file(&plain_file, &cipher_file).unwrap();
+            let mut deciphered_text = Vec::new();
+            streaming
+                .decipher_seekable(
+                    std::fs::File::open(&cipher_file).unwrap(),
+                    &mut deciphered_text,
+                )
+                .unwrap();
+            assert_eq!(deciphered_text, plain_text, "{:?} {}", mode, len);
+
+            streaming.cipher_file(&plain_file, &cipher_file).unwrap();
+            mapped
+                .decipher_file(&cipher_file, &deciphered_file)
+                .unwrap();
+            assert_eq!(
+                std::fs::read(&deciphered_file).unwrap(),
+                plain_text,
+                "{:?} {}",
+                mode,
+                len
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_positional_io_matches_streaming() {
+        let modes = [
+            Mode::Ecb,
+            Mode::Ctr(CounterLayout::Ctr64),
+            // Streamed, since their blocks depend on each other
+            Mode::Cbc,
+            Mode::Gcm,
+        ];
+        let (_dir, [plain_file, cipher_file, deciphered_file]) = temp_files();
+
+        for (mode, backend, len) in modes.into_iter().flat_map(|mode| {
+            [Backend::Rayon, Backend::Queue, Backend::Sequential]
+                .into_iter()
+                .flat_map(move |backend| [1, 32, 1000, 4099].map(|len| (mode, backend, len)))
+        }) {
+            let plain_text: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            std::fs::write(&plain_file, &plain_text).unwrap();
+            // Parts of 5 blocks, so that the threads go through several rounds of them
+            let cipher_config = |positional_io| AESCipherConfig {
+                n_threads: 3,
+                backend,
+                buffer_blocks: 5,
+                positional_io,
+                ..config(mode, PaddingScheme::Pkcs7)
+            };
+            let mut streaming = AESCipher::new(&CIPHER_KEY, cipher_config(false)).unwrap();
+            let mut positional = AESCipher::new(&CIPHER_KEY, cipher_config(true)).unwrap();
+
+            positional.cipher_file(&plain_file, &cipher_file).unwrap();
+            let mut deciphered_text = Vec::new();
+            streaming
+                .decipher_seekable(File::open(&cipher_file).unwrap(), &mut deciphered_text)
+                .unwrap();
+            assert_eq!(
+                deciphered_text, plain_text,
+                "{:?} {:?} {}",
+                mode, backend, len
+            );
+
+            streaming.cipher_file(&plain_file, &cipher_file).unwrap();
+            positional
+                .decipher_file(&cipher_file, &deciphered_file)
+                .unwrap();
+            assert_eq!(
+                std::fs::read(&deciphered_file).unwrap(),
+                plain_text,
+                "{:?} {:?} {}",
+                mode,
+                backend,
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn test_mmap_errors() {
+        let (_dir, [plain_file, cipher_file, deciphered_file]) = temp_files();
+        let cipher_config = |mode, padding| AESCipherConfig {
+            mmap: true,
+            ..config(mode, padding)
+        };
+
+        std::fs::write(&plain_file, [1; 20]).unwrap();
+        let mut cipher =
+            AESCipher::new(&CIPHER_KEY, cipher_config(Mode::Ecb, PaddingScheme::None)).unwrap();
+        let result = cipher.cipher_file(&plain_file, &cipher_file);
+        assert!(matches!(result, Err(Error::InvalidPadding(_))));
+
+        let mut cipher =
+            AESCipher::new(&CIPHER_KEY, cipher_config(Mode::Gcm, PaddingScheme::Pkcs7)).unwrap();
+        cipher.cipher_file(&plain_file, &cipher_file).unwrap();
+        let mut cipher_text = std::fs::read(&cipher_file).unwrap();
+        let last = cipher_text.len() - 1;
+        cipher_text[last] ^= 1;
+        std::fs::write(&cipher_file, &cipher_text).unwrap();
+        let result = cipher.decipher_file(&cipher_file, &deciphered_file);
+        assert!(matches!(result, Err(Error::AuthenticationFailed)));
+        // No plaintext is released when the ciphertext is not authentic
+        assert!(!std::path::Path::new(&deciphered_file).exists());
+    }
+
+    #[test]
+    fn test_mmap_falls_back_to_streaming() {
+        let (_dir, [plain_file, cipher_file, deciphered_file]) = temp_files();
+        let cipher_config = AESCipherConfig {
+            mmap: true,
+            ..config(Mode::Cbc, PaddingScheme::Pkcs7)
+        };
+        let mut cipher = AESCipher::new(&CIPHER_KEY, cipher_config).unwrap();
+
+        // Empty files cannot be mapped
+        std::fs::write(&plain_file, []).unwrap();
+        cipher.cipher_file(&plain_file, &cipher_file).unwrap();
+        cipher
+            .decipher_file(&cipher_file, &deciphered_file)
+            .unwrap();
+        assert!(std::fs::read(&deciphered_file).unwrap().is_empty());
+
+        // Neither can special files
+        if std::path::Path::new("/dev/null").exists() {
+            std::fs::write(&plain_file, "Hello World!").unwrap();
+            cipher.cipher_file(&plain_file, "/dev/null").unwrap();
+            cipher
+                .decipher_file("/dev/null", &deciphered_file)
+                .unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_phase_times() {
+        let plain_text = "Hello World! ".repeat(1000).into_bytes();
+        for pipelined in [false, true] {
+            let cipher_config = AESCipherConfig {
+                buffer_blocks: 64,
+                pipelined,
+                ..config(Mode::Cbc, PaddingScheme::Pkcs7)
+            };
+            let mut cipher = AESCipher::new(&CIPHER_KEY, cipher_config).unwrap();
+
+            let mut cipher_text = Vec::new();
+            cipher
+                .cipher(plain_text.as_slice(), &mut cipher_text)
+                .unwrap();
+            let phase_times = cipher.take_phase_times();
+            for phase in Phase::ALL {
+                assert!(phase_times.get(phase) > Duration::ZERO, "{:?}", phase);
+            }
+            assert_eq!(cipher.take_phase_times(), PhaseTimes::default());
+        }
+    }
+
+    #[test]
+    fn test_config_reports_the_settings_in_use() {
+        let cipher_config = AESCipherConfig {
+            n_threads: 0,
+            implementation: Implementation::Auto,
+            ..Default::default()
+        };
+        let cipher = AESCipher::new(&CIPHER_KEY, cipher_config).unwrap();
+        assert!(cipher.config().n_threads >= 1);
+        assert_ne!(cipher.config().implementation, Implementation::Auto);
+
+        let invalid_configs = [
+            AESCipherConfig {
+                task_blocks: 0,
+                ..Default::default()
+            },
+            AESCipherConfig {
+                buffer_blocks: 0,
+                ..Default::default()
+            },
+            AESCipherConfig {
+                buffer_blocks: MAX_BUFFER_BLOCKS + 1,
+                ..Default::default()
+            },
+        ];
+        for cipher_config in invalid_configs {
+            assert!(matches!(
+                AESCipher::new(&CIPHER_KEY, cipher_config),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_implementations_are_interchangeable() {
+        let plain_text = "Hello World! ".repeat(1000).into_bytes();
+        let implementations = [
+            Implementation::Reference,
+            Implementation::TTable,
+            Implementation::Bitsliced,
+            Implementation::AesNi,
+        ]
+        .into_iter()
+        .filter(Implementation::is_supported);
+
+        for mode in [Mode::Ecb, Mode::Cbc, Mode::Gcm] {
+            for cipher_implementation in implementations.clone() {
+                for decipher_implementation in implementations.clone() {
+                    let new_cipher = |implementation| {
+                        let cipher_config = AESCipherConfig {
+                            implementation,
+                            ..config(mode, PaddingScheme::Pkcs7)
+                        };
+                        AESCipher::new(&CIPHER_KEY, cipher_config).unwrap()
+                    };
+
+                    let mut cipher_text = Vec::new();
+                    new_cipher(cipher_implementation)
+                        .cipher(plain_text.as_slice(), &mut cipher_text)
+                        .unwrap();
+                    let mut deciphered_text = Vec::new();
+                    new_cipher(decipher_implementation)
+                        .decipher_seekable(Cursor::new(&cipher_text), &mut deciphered_text)
+                        .unwrap();
+
+                    assert_eq!(deciphered_text, plain_text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decipher_uses_the_settings_from_the_header() {
+        let plain_text = "Hello World! ".repeat(100).into_bytes();
+        let (cipher_text, _) = round_trip(Mode::Cbc, &plain_text);
+
+        let mut cipher =
+            AESCipher::new(&CIPHER_KEY, config(Mode::Gcm, PaddingScheme::None)).unwrap();
+        let mut deciphered_text = Vec::new();
+        cipher
+            .decipher(Cursor::new(&cipher_text), &mut deciphered_text)
+            .unwrap();
+
+        assert_eq!(deciphered_text, plain_text);
+    }
+
+    #[test]
+    fn test_decipher_with_wrong_key_size() {
+        let (cipher_text, _) = round_trip(Mode::Ecb, b"Hello World!");
+
+        let mut cipher = AESCipher::new(&[0; 32], config(Mode::Ecb, PaddingScheme::Pkcs7)).unwrap();
+        let result = cipher.decipher(Cursor::new(&cipher_text), Vec::new());
+
+        assert!(matches!(result, Err(Error::KeyMismatch(_))));
+    }
+
+    #[test]
+    fn test_decipher_without_header() {
+        let mut cipher =
+            AESCipher::new(&CIPHER_KEY, config(Mode::Ecb, PaddingScheme::Pkcs7)).unwrap();
+        let result = cipher.decipher(Cursor::new([0u8; 64]), Vec::new());
+
+        assert!(matches!(result, Err(Error::InvalidHeader(_))));
+    }
+
+    #[test]
+    fn test_decipher_without_seeking() {
+        let plain_text = "Hello World! ".repeat(100).into_bytes();
+        let modes = [
+            Mode::Ecb,
+            Mode::Cbc,
+            Mode::Ctr(CounterLayout::Ctr128),
+            Mode::Gcm,
+        ];
+
+        for mode in modes {
+            let (cipher_text, _) = round_trip(mode, &plain_text);
+            let mut cipher =
+                AESCipher::new(&CIPHER_KEY, config(mode, PaddingScheme::Pkcs7)).unwrap();
+
+            // A slice can be read but not seeked, as a pipe
+            let mut deciphered_text = Vec::new();
+            let result = cipher.decipher(cipher_text.as_slice(), &mut deciphered_text);
+            match mode {
+                Mode::Gcm => {
+                    assert!(matches!(result, Err(Error::Unsupported(_))));
+                    assert!(deciphered_text.is_empty());
+                }
+                _ => {
+                    result.unwrap();
+                    assert_eq!(deciphered_text, plain_text, "{:?}", mode);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_passphrase_round_trip() {
+        let plain_text = "Hello World! ".repeat(100).into_bytes();
+        let new_cipher = |passphrase: &[u8]| {
+            AESCipher::new_with_passphrase(
+                passphrase,
+                KeySize::Aes256,
+                1000,
+                config(Mode::Gcm, PaddingScheme::Pkcs7),
+            )
+            .unwrap()
+        };
+
+        let mut cipher_text = Vec::new();
+        new_cipher(b"correct horse")
+            .cipher(plain_text.as_slice(), &mut cipher_text)
+            .unwrap();
+
+        // Another cipher has a different salt, so it has to derive the key from the header
+        let mut deciphered_text = Vec::new();
+        new_cipher(b"correct horse")
+            .decipher_seekable(Cursor::new(&cipher_text), &mut deciphered_text)
+            .unwrap();
+        assert_eq!(deciphered_text, plain_text);
+
+        let result =
+            new_cipher(b"wrong horse").decipher_seekable(Cursor::new(&cipher_text), Vec::new());
+        assert!(matches!(result, Err(Error::AuthenticationFailed)));
+
+        let mut cipher = AESCipher::new(&[0; 32], config(Mode::Gcm, PaddingScheme::Pkcs7)).unwrap();
+        let result = cipher.decipher_seekable(Cursor::new(&cipher_text), Vec::new());
+        assert!(matches!(result, Err(Error::KeyMismatch(_))));
     }
 }
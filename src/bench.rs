@@ -0,0 +1,232 @@
+/*
+Statistics of the iteration times of the bench command, which are printed and published as
+gauges, so that the spread of the runs is visible and not only their total. The sweep command
+compares them across thread counts.
+ */
+use aes_rust::metrics_logger::MetricsLogger;
+
+/// Summary of the times of the timed iterations, in seconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub iterations: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    /// Sample standard deviation, which is 0 with a single iteration
+    pub std_dev: f64,
+    pub p95: f64,
+    pub p99: f64,
+    /// Millions of bytes of the input processed per second, at the mean iteration time
+    pub throughput: f64,
+}
+
+impl Summary {
+    /// Summarize the times of the iterations, each of which processed `iteration_bytes` bytes
+    /// of input. Returns `None` if there are no iterations.
+    pub fn new(iteration_times: &[f64], iteration_bytes: u64) -> Option<Self> {
+        if iteration_times.is_empty() {
+            return None;
+        }
+
+        let mut times = iteration_times.to_vec();
+        times.sort_by(f64::total_cmp);
+        let n = times.len();
+        let mean = times.iter().sum::<f64>() / n as f64;
+        let median = match n % 2 {
+            0 => (times[n / 2 - 1] + times[n / 2]) / 2.0,
+            _ => times[n / 2],
+        };
+        let std_dev = match n {
+            1 => 0.0,
+            _ => {
+                let variance = times.iter().map(|time| (time - mean).powi(2)).sum::<f64>();
+                (variance / (n - 1) as f64).sqrt()
+            }
+        };
+        let throughput = match mean > 0.0 {
+            true => iteration_bytes as f64 / 1e6 / mean,
+            false => 0.0,
+        };
+
+        Some(Summary {
+            iterations: n,
+            min: times[0],
+            max: times[n - 1],
+            mean,
+            median,
+            std_dev,
+            p95: percentile(&times, 95.0),
+            p99: percentile(&times, 99.0),
+            throughput,
+        })
+    }
+
+    /// Name and value of each statistic of the times, as printed and published
+    fn times(&self) -> [(&'static str, f64); 7] {
+        [
+            ("min", self.min),
+            ("max", self.max),
+            ("mean", self.mean),
+            ("median", self.median),
+            ("std_dev", self.std_dev),
+            ("p95", self.p95),
+            ("p99", self.p99),
+        ]
+    }
+
+    /// Publish every statistic as a gauge named `<metric>.<statistic>`, and the throughput as
+    /// `<metric>.throughput_mb_per_s`
+    pub fn log(&self, logger: &dyn MetricsLogger, metric: &str) {
+        for (name, value) in self.times() {
+            logger.gauge(&format!("{}.{}", metric, name), value);
+        }
+        logger.gauge(&format!("{}.throughput_mb_per_s", metric), self.throughput);
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Iteration times over {} runs:", self.iterations)?;
+        for (name, value) in self.times() {
+            write!(f, "\n  {:<10} {:.6}s", name, value)?;
+        }
+        write!(f, "\n  throughput {:.2} MB/s", self.throughput)
+    }
+}
+
+/// Bench of one thread count of a sweep, compared with the single-thread baseline
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepResult {
+    pub n_threads: usize,
+    pub summary: Summary,
+    /// Mean time of the baseline divided by the mean time with `n_threads`
+    pub speedup: f64,
+    /// Speedup divided by the number of threads, which is 1 with a linear speedup
+    pub efficiency: f64,
+}
+
+impl SweepResult {
+    /// Compare the summary of each thread count with the one of a single thread, which has to be
+    /// among them. Returns `None` if it is not.
+    pub fn compare(summaries: Vec<(usize, Summary)>) -> Option<Vec<Self>> {
+        let baseline = summaries
+            .iter()
+            .find(|(n_threads, _)| *n_threads == 1)
+            .map(|(_, summary)| summary.mean)?;
+
+        let results = summaries
+            .into_iter()
+            .map(|(n_threads, summary)| {
+                let speedup = match summary.mean > 0.0 {
+                    true => baseline / summary.mean,
+                    false => 0.0,
+                };
+                SweepResult {
+                    n_threads,
+                    summary,
+                    speedup,
+                    efficiency: speedup / n_threads as f64,
+                }
+            })
+            .collect();
+        Some(results)
+    }
+
+    /// Publish the statistics of the iteration times as `<metric>.iteration_time.<statistic>`,
+    /// and the speedup and efficiency as `<metric>.speedup` and `<metric>.efficiency`
+    pub fn log(&self, logger: &dyn MetricsLogger, metric: &str) {
+        self.summary
+            .log(logger, &format!("{}.iteration_time", metric));
+        logger.gauge(&format!("{}.speedup", metric), self.speedup);
+        logger.gauge(&format!("{}.efficiency", metric), self.efficiency);
+    }
+}
+
+/// Table of the results of a sweep, with one row per thread count
+pub fn sweep_table(results: &[SweepResult]) -> String {
+    let mut table = format!(
+        "{:>7}  {:>10}  {:>10}  {:>10}  {:>12}  {:>8}  {:>10}",
+        "threads", "mean (s)", "median (s)", "std dev", "MB/s", "speedup", "efficiency"
+    );
+    for result in results {
+        let summary = &result.summary;
+        table.push_str(&format!(
+            "\n{:>7}  {:>10.6}  {:>10.6}  {:>10.6}  {:>12.2}  {:>7.2}x  {:>9.1}%",
+            result.n_threads,
+            summary.mean,
+            summary.median,
+            summary.std_dev,
+            summary.throughput,
+            result.speedup,
+            100.0 * result.efficiency
+        ));
+    }
+    table
+}
+
+/// Percentile of the sorted times with the nearest-rank method, so that it is always one of the
+/// measured times
+fn percentile(sorted_times: &[f64], percent: f64) -> f64 {
+    let rank = (percent / 100.0 * sorted_times.len() as f64).ceil() as usize;
+    sorted_times[rank.clamp(1, sorted_times.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        // 1..=100 ms, shuffled
+        let times: Vec<f64> = (1..=100)
+            .map(|i| ((i * 37) % 100 + 1) as f64 / 1000.0)
+            .collect();
+        let summary = Summary::new(&times, 5_000_000).unwrap();
+
+        assert_eq!(summary.iterations, 100);
+        assert_eq!(summary.min, 0.001);
+        assert_eq!(summary.max, 0.1);
+        assert!((summary.mean - 0.0505).abs() < 1e-12);
+        assert!((summary.median - 0.0505).abs() < 1e-12);
+        assert!((summary.std_dev - 0.0290115).abs() < 1e-6);
+        assert_eq!(summary.p95, 0.095);
+        assert_eq!(summary.p99, 0.099);
+        assert!((summary.throughput - 5.0 / 0.0505).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_summary_of_few_iterations() {
+        assert_eq!(Summary::new(&[], 100), None);
+
+        let summary = Summary::new(&[2.0], 4_000_000).unwrap();
+        assert_eq!((summary.min, summary.max, summary.median), (2.0, 2.0, 2.0));
+        assert_eq!((summary.std_dev, summary.p95, summary.p99), (0.0, 2.0, 2.0));
+        assert_eq!(summary.throughput, 2.0);
+
+        let summary = Summary::new(&[3.0, 1.0], 0).unwrap();
+        assert_eq!((summary.median, summary.p95), (2.0, 3.0));
+    }
+
+    #[test]
+    fn test_sweep() {
+        let summary = |mean: f64| Summary::new(&[mean], 8_000_000).unwrap();
+        // The baseline does not have to come first
+        let summaries = vec![(2, summary(1.0)), (1, summary(1.6)), (4, summary(0.5))];
+        let results = SweepResult::compare(summaries).unwrap();
+
+        let speedups: Vec<_> = results.iter().map(|result| result.speedup).collect();
+        assert_eq!(speedups, [1.6, 1.0, 3.2]);
+        let efficiencies: Vec<_> = results.iter().map(|result| result.efficiency).collect();
+        assert_eq!(efficiencies, [0.8, 1.0, 0.8]);
+        assert_eq!(
+            sweep_table(&results),
+            "threads    mean (s)  median (s)     std dev          MB/s   speedup  efficiency\n      \
+                   2    1.000000    1.000000    0.000000          8.00     1.60x       80.0%\n      \
+                   1    1.600000    1.600000    0.000000          5.00     1.00x      100.0%\n      \
+                   4    0.500000    0.500000    0.000000         16.00     3.20x       80.0%"
+        );
+
+        assert_eq!(SweepResult::compare(vec![(2, summary(1.0))]), None);
+    }
+}
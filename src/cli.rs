@@ -0,0 +1,422 @@
+/*
+Command-line interface. The subcommand and its flags override the configuration read from the
+environment, and running without arguments keeps the environment-driven behaviour used by the
+docker images.
+ */
+use crate::config::Config;
+use aes_rust::key::KeySource;
+use aes_rust::metrics_logger::MetricsBackend;
+
+pub const USAGE: &str = "Usage: aes_rust [<command> [options]]
+
+Commands:
+    encrypt     Encrypt the input file into the output file
+    decrypt     Decrypt the input file into the output file
+    verify      Encrypt and decrypt the input file, and check that the result matches it
+    bench       Encrypt and decrypt the input file REPEAT times and report the elapsed time
+    sweep       Run bench with each of the --sweep thread counts and report the speedup and
+                parallel efficiency of each against a single thread
+    keygen      Write a new random hex key to the output file
+    help        Print this message
+
+Without a command, the files to encrypt and decrypt are taken from PLAIN_TEXT, ENCRYPTED_TEXT
+and DECRYPTED_TEXT. Options override the matching environment variables.
+
+Options:
+    -i, --input <path>          Input file
+    -o, --output <path>         Output file. Optional for verify and bench, which keep the
+                                encrypted file there instead of in a temporary file
+    -k, --key <hex>             Cipher key as a hex string
+        --key-file <path>       File with the cipher key, as a hex string or raw bytes
+        --passphrase <text>     Passphrase the cipher key is derived from. Other users can
+                                see it in the process list and it stays in the shell history,
+                                so prefer --key-prompt, --key-file or PASSPHRASE
+        --key-prompt            Read the hex cipher key from the standard input
+        --key-size <bits>       Size of the generated or derived key: 128, 192 or 256
+        --kdf-iterations <n>    PBKDF2 iterations used when encrypting with a passphrase
+    -m, --mode <mode>           Mode of operation: ecb, cbc, ctr, ctr64, ctr32 or gcm
+    -p, --padding <scheme>      Padding scheme: pkcs7, iso7816, ansix923, zero or none
+    -t, --threads <n>           Number of threads
+    -b, --backend <name>        Concurrency backend: rayon, scoped, queue or sequential
+        --task-blocks <n>       Minimum number of blocks in each parallel task
+        --buffer-blocks <n>     Number of blocks read, ciphered and written at a time
+    -a, --implementation <name> AES implementation: auto (AES-NI if the processor has it,
+                                ttable otherwise), reference, ttable, bitsliced or aesni
+    -r, --repeat <n>            Number of timed iterations of bench
+    -w, --warmup <n>            Number of untimed iterations of bench run before them
+    -s, --sweep <counts>        Thread counts of sweep, as a list or range such as 1,2,4,8
+                                or 1..=16
+        --pipelined             Read and write the files on their own threads, overlapping
+                                with the ciphering
+        --mmap                  Map regular files into memory instead of streaming them
+        --positional-io         Read and write regular files at the offset of each task, in
+                                parallel, in ECB and CTR modes
+        --range <start>:<len>   Only decrypt a byte range of the plaintext (CTR mode)
+        --publish-metrics       Send the metrics of bench to the metrics backends
+        --metrics <list>        Publish the metrics to a comma-separated list of backends:
+                                statsd, stdout, jsonl, csv, prometheus or noop
+        --statsd-host <host>    Host of the StatsD server
+        --statsd-port <port>    UDP port of the StatsD server
+        --metrics-prefix <name> Prefix of the metric names
+        --metrics-json-file <path>
+                                File the jsonl backend appends the metrics to
+        --metrics-csv-file <path>
+                                File the csv backend appends the metrics to
+        --prometheus-file <path>
+                                Text exposition file the prometheus backend writes
+        --run-id <id>           Value of the run_id tag of the metrics, random by default
+    -h, --help                  Print this message";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Encrypt,
+    Decrypt,
+    Verify,
+    Bench,
+    Sweep,
+    Keygen,
+    Help,
+    /// No command was given, so the files to process are selected by the environment
+    FromEnv,
+}
+
+impl std::str::FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "encrypt" => Ok(Command::Encrypt),
+            "decrypt" => Ok(Command::Decrypt),
+            "verify" => Ok(Command::Verify),
+            "bench" => Ok(Command::Bench),
+            "sweep" => Ok(Command::Sweep),
+            "keygen" => Ok(Command::Keygen),
+            "help" | "-h" | "--help" => Ok(Command::Help),
+            _ => Err(format!("Unknown command: {}\n\n{}", s, USAGE)),
+        }
+    }
+}
+
+/// Parsed command line
+#[derive(Debug)]
+pub struct Cli {
+    pub command: Command,
+    /// Private directory of the temporary files of the command, which is removed with them when
+    /// the command line is dropped
+    temp_dir: Option<tempfile::TempDir>,
+}
+
+impl Cli {
+    /// Parse the arguments, without the program name, and apply them to the configuration
+    pub fn parse<I>(args: I, config: &mut Config) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = args.into_iter();
+        let command = match args.next() {
+            Some(command) => command.parse()?,
+            None => Command::FromEnv,
+        };
+
+        let mut input = None;
+        let mut output = None;
+        while let Some(arg) = args.next() {
+            // Long options also accept the `--option=value` syntax
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or(format!("Missing value for {}", flag))
+            };
+
+            match flag.as_str() {
+                "-i" | "--input" => input = Some(value()?),
+                "-o" | "--output" => output = Some(value()?),
+                "-k" | "--key" => config.key_source = Some(KeySource::Hex(value()?)),
+                "--key-file" => config.key_source = Some(KeySource::File(value()?)),
+                "--passphrase" => config.key_source = Some(KeySource::Passphrase(value()?)),
+                "--key-prompt" => config.key_source = Some(KeySource::Prompt),
+                "--key-size" => config.key_size = value()?.parse()?,
+                "--kdf-iterations" => config.kdf_iterations = parse_number(&flag, &value()?)?,
+                "-m" | "--mode" => config.mode = value()?.parse()?,
+                "-p" | "--padding" => config.padding = value()?.parse()?,
+                "-t" | "--threads" => config.n_threads = parse_number(&flag, &value()?)?,
+                "-b" | "--backend" => config.backend = value()?.parse()?,
+                "--task-blocks" => config.task_blocks = parse_number(&flag, &value()?)?,
+                "--buffer-blocks" => config.buffer_blocks = parse_number(&flag, &value()?)?,
+                "-a" | "--implementation" => config.implementation = value()?.parse()?,
+                "-r" | "--repeat" => config.repeat = parse_number(&flag, &value()?)?,
+                "-w" | "--warmup" => config.warmup = parse_number(&flag, &value()?)?,
+                "-s" | "--sweep" => {
+                    config.sweep_threads = Some(Config::parse_thread_counts(&value()?)?)
+                }
+                "--pipelined" => config.pipelined = true,
+                "--mmap" => config.mmap = true,
+                "--positional-io" => config.positional_io = true,
+                "--range" => config.decrypt_range = Some(Config::parse_range(&value()?)?),
+                "--publish-metrics" => config.publish_metrics = true,
+                "--metrics" => {
+                    config.metrics_backends = MetricsBackend::parse_list(&value()?)?;
+                    config.publish_metrics = true;
+                }
+                "--statsd-host" => config.statsd_host = value()?,
+                "--statsd-port" => config.statsd_port = parse_number(&flag, &value()?)?,
+                "--metrics-prefix" | "--statsd-prefix" => config.metrics_prefix = value()?,
+                "--metrics-json-file" => config.metrics_json_file = value()?,
+                "--metrics-csv-file" => config.metrics_csv_file = value()?,
+                "--prometheus-file" => config.prometheus_file = value()?,
+                "--run-id" => config.run_id = value()?,
+                "-h" | "--help" => return Ok(Self::new(Command::Help)),
+                _ => return Err(format!("Unknown option: {}\n\n{}", flag, USAGE)),
+            }
+        }
+
+        let mut cli = Self::new(command);
+        cli.apply_files(input, output, config)?;
+        Ok(cli)
+    }
+
+    fn new(command: Command) -> Self {
+        Self {
+            command,
+            temp_dir: None,
+        }
+    }
+
+    /// Set the files of the configuration from the input and output of the command
+    fn apply_files(
+        &mut self,
+        input: Option<String>,
+        output: Option<String>,
+        config: &mut Config,
+    ) -> Result<(), String> {
+        let command = self.command.name();
+        let required = |file: Option<String>, name: &str| {
+            file.ok_or(format!("The {} command requires an {} file", command, name))
+        };
+
+        // Only the keygen command writes a key, even if KEYGEN is set
+        if self.command != Command::FromEnv {
+            config.keygen_file = None;
+        }
+
+        match self.command {
+            Command::Encrypt => {
+                config.input_file = Some(required(input, "input")?);
+                config.encrypted_file = Some(required(output, "output")?);
+                config.decrypted_file = None;
+            }
+            Command::Decrypt => {
+                config.input_file = None;
+                config.encrypted_file = Some(required(input, "input")?);
+                config.decrypted_file = Some(required(output, "output")?);
+            }
+            Command::Verify | Command::Bench | Command::Sweep => {
+                config.input_file = Some(required(input, "input")?);
+                config.encrypted_file = Some(match output {
+                    Some(output) => output,
+                    None => self.temp_file("encrypted")?,
+                });
+                config.decrypted_file = Some(self.temp_file("decrypted")?);
+                if self.command == Command::Verify {
+                    config.decrypt_range = None;
+                }
+            }
+            Command::Keygen => config.keygen_file = Some(required(output, "output")?),
+            Command::Help | Command::FromEnv => {}
+        }
+        Ok(())
+    }
+
+    /// Path of a temporary file in a directory created with a random name and only accessible
+    /// to the user, so that other processes cannot collide with it or create the file first
+    fn temp_file(&mut self, name: &str) -> Result<String, String> {
+        let temp_dir = match &mut self.temp_dir {
+            Some(temp_dir) => temp_dir,
+            temp_dir => temp_dir.insert(
+                tempfile::Builder::new()
+                    .prefix("aes_rust_")
+                    .tempdir()
+                    .map_err(|e| format!("Error while creating a temporary directory: {}", e))?,
+            ),
+        };
+        Ok(temp_dir.path().join(name).to_string_lossy().into_owned())
+    }
+}
+
+impl Command {
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Encrypt => "encrypt",
+            Command::Decrypt => "decrypt",
+            Command::Verify => "verify",
+            Command::Bench => "bench",
+            Command::Sweep => "sweep",
+            Command::Keygen => "keygen",
+            Command::Help => "help",
+            Command::FromEnv => "default",
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number for {}: {}", flag, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes_rust::aes_block_cipher::{Implementation, KeySize};
+    use aes_rust::concurrency::Backend;
+    use aes_rust::modes::Mode;
+
+    fn parse(args: &[&str]) -> Result<(Cli, Config), String> {
+        // Not read from the environment, so that the variables of the developer do not matter
+        let mut config = Config::default();
+        let cli = Cli::parse(args.iter().map(|arg| arg.to_string()), &mut config)?;
+        Ok((cli, config))
+    }
+
+    #[test]
+    fn test_parse_encrypt() {
+        let (cli, config) = parse(&[
+            "encrypt",
+            "-i",
+            "plain.txt",
+            "--output=encrypted.bin",
+            "--mode",
+            "gcm",
+            "-t",
+            "8",
+            "--passphrase",
+            "secret",
+            "--key-size",
+            "256",
+            "--pipelined",
+            "--mmap",
+            "--positional-io",
+            "-w",
+            "3",
+            "--metrics",
+            "stdout,csv",
+            "--metrics-prefix",
+            "aes",
+            "--metrics-csv-file=runs.csv",
+            "--statsd-host=localhost",
+            "--statsd-port",
+            "9125",
+            "--run-id",
+            "baseline",
+            "--backend=queue",
+            "--task-blocks",
+            "64",
+            "--buffer-blocks=1024",
+            "-a",
+            "ttable",
+        ])
+        .unwrap();
+
+        assert_eq!(cli.command, Command::Encrypt);
+        assert_eq!(config.input_file.as_deref(), Some("plain.txt"));
+        assert_eq!(config.encrypted_file.as_deref(), Some("encrypted.bin"));
+        assert_eq!(config.decrypted_file, None);
+        assert_eq!(config.mode, Mode::Gcm);
+        assert_eq!(config.n_threads, 8);
+        assert_eq!(
+            config.key_source,
+            Some(KeySource::Passphrase("secret".to_string()))
+        );
+        assert_eq!(config.key_size, KeySize::Aes256);
+        assert!(config.pipelined);
+        assert!(config.mmap);
+        assert!(config.positional_io);
+        assert_eq!(config.warmup, 3);
+        assert!(config.publish_metrics);
+        assert_eq!(
+            config.metrics_backends,
+            [MetricsBackend::Stdout, MetricsBackend::Csv]
+        );
+        assert_eq!(config.metrics_prefix, "aes");
+        assert_eq!(config.metrics_csv_file, "runs.csv");
+        assert_eq!(config.statsd_host, "localhost");
+        assert_eq!(config.statsd_port, 9125);
+        assert_eq!(config.run_id, "baseline");
+        assert_eq!(config.backend, Backend::Queue);
+        assert_eq!(config.task_blocks, 64);
+        assert_eq!(config.buffer_blocks, 1024);
+        assert_eq!(config.implementation, Implementation::TTable);
+    }
+
+    #[test]
+    fn test_parse_decrypt_and_verify() {
+        let (_, config) = parse(&["decrypt", "-i", "in", "-o", "out", "--range", "5:10"]).unwrap();
+        assert_eq!(config.input_file, None);
+        assert_eq!(config.encrypted_file.as_deref(), Some("in"));
+        assert_eq!(config.decrypted_file.as_deref(), Some("out"));
+        assert_eq!(config.decrypt_range, Some((5, 10)));
+
+        let (cli, config) = parse(&["verify", "-i", "in"]).unwrap();
+        let temp_dir = cli.temp_dir.as_ref().unwrap().path().to_path_buf();
+        let temp_file = |name| Some(temp_dir.join(name).to_string_lossy().into_owned());
+        assert_eq!(config.encrypted_file, temp_file("encrypted"));
+        assert_eq!(config.decrypted_file, temp_file("decrypted"));
+        assert!(temp_dir.is_dir());
+        drop(cli);
+        assert!(!temp_dir.exists());
+    }
+
+    #[test]
+    fn test_parse_sweep() {
+        let (cli, config) = parse(&["sweep", "-i", "in", "--sweep", "1,2,4,8"]).unwrap();
+        assert_eq!(cli.command, Command::Sweep);
+        assert!(cli.temp_dir.is_some());
+        assert_eq!(config.sweep_threads, Some(vec![1, 2, 4, 8]));
+
+        let (_, config) = parse(&["sweep", "-i", "in", "-s", "1..=4"]).unwrap();
+        assert_eq!(config.sweep_threads, Some(vec![1, 2, 3, 4]));
+        let (_, config) = parse(&["sweep", "-i", "in", "--sweep=2..4, 16"]).unwrap();
+        assert_eq!(config.sweep_threads, Some(vec![2, 3, 16]));
+
+        for counts in ["", "0,1", "4..=2", "3..3", "1..=many"] {
+            assert!(parse(&["sweep", "-i", "in", "--sweep", counts]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_without_command_and_help() {
+        assert_eq!(parse(&[]).unwrap().0.command, Command::FromEnv);
+        assert_eq!(parse(&["--help"]).unwrap().0.command, Command::Help);
+        assert_eq!(
+            parse(&["bench", "-i", "in", "-h"]).unwrap().0.command,
+            Command::Help
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse(&["compress"])
+            .unwrap_err()
+            .contains("Unknown command"));
+        assert!(parse(&["encrypt", "--fast"])
+            .unwrap_err()
+            .contains("Unknown option"));
+        assert!(parse(&["encrypt", "-i"])
+            .unwrap_err()
+            .contains("Missing value"));
+        assert!(parse(&["encrypt", "-i", "in"])
+            .unwrap_err()
+            .contains("output file"));
+        assert!(parse(&["bench", "-i", "in", "-t", "many"])
+            .unwrap_err()
+            .contains("Invalid number"));
+        assert!(parse(&["bench", "-i", "in", "-m", "xts"]).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+/*
+Concurrency backends that process the blocks of a batch in parallel, so that the same cipher
+can be run on different threading models and their performance compared.
+ */
+pub mod queue_executor;
+pub mod rayon_executor;
+pub mod scoped_executor;
+pub mod sequential_executor;
+
+pub use queue_executor::QueueExecutor;
+pub use rayon_executor::RayonExecutor;
+pub use scoped_executor::ScopedExecutor;
+pub use sequential_executor::SequentialExecutor;
+
+use crate::Result;
+
+/// Runs a function over the chunks of a buffer, possibly on several threads
+pub trait Executor: Send + Sync {
+    /// Split the data into chunks of `chunk_len` bytes, of which only the last one may be
+    /// shorter, and call `f` with the index and the contents of each of them. The chunks may be
+    /// processed in any order and on any thread.
+    fn for_each_chunk(
+        &self,
+        data: &mut [u8],
+        chunk_len: usize,
+        f: &(dyn Fn(usize, &mut [u8]) + Sync),
+    );
+}
+
+impl<'a> dyn Executor + 'a {
+    /// Split the blocks into chunks of `chunk_blocks` blocks, and call `f` with the index of the
+    /// first block of each chunk and its blocks
+    pub fn for_each_block_chunk<F>(&self, blocks: &mut [[u8; 16]], chunk_blocks: usize, f: F)
+    where
+        F: Fn(usize, &mut [[u8; 16]]) + Sync,
+    {
+        self.for_each_chunk(blocks.as_flattened_mut(), 16 * chunk_blocks, &|i, chunk| {
+            f(i * chunk_blocks, chunk.as_chunks_mut().0)
+        });
+    }
+}
+
+/// Threading model used to cipher the blocks of each batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Rayon thread pool, with work stealing
+    Rayon,
+    /// Threads spawned with `std::thread::scope` for each batch, which is split in one
+    /// contiguous part per thread
+    Scoped,
+    /// Threads spawned for each batch that take tasks from a shared `std::sync::mpsc` queue
+    Queue,
+    /// Everything runs on the current thread, as a baseline
+    Sequential,
+}
+
+impl Backend {
+    /// Name of the backend, as used in the configuration and the metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Rayon => "rayon",
+            Backend::Scoped => "scoped",
+            Backend::Queue => "queue",
+            Backend::Sequential => "sequential",
+        }
+    }
+
+    /// Create an executor of this backend with `n_threads` threads, whose tasks have at least
+    /// `task_blocks` blocks. With 0 threads, the number of threads is the available parallelism.
+    /// The sequential backend always uses one thread, in a single task.
+    pub fn executor(&self, n_threads: usize, task_blocks: usize) -> Result<Box<dyn Executor>> {
+        let n_threads = resolve_n_threads(n_threads);
+        let min_task_len = 16 * task_blocks;
+        Ok(match self {
+            Backend::Rayon => Box::new(RayonExecutor::new(n_threads, min_task_len)?),
+            Backend::Scoped => Box::new(ScopedExecutor::new(n_threads, min_task_len)),
+            Backend::Queue => Box::new(QueueExecutor::new(n_threads, min_task_len)),
+            Backend::Sequential => Box::new(SequentialExecutor),
+        })
+    }
+}
+
+impl std::str::FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rayon" => Ok(Backend::Rayon),
+            "scoped" => Ok(Backend::Scoped),
+            "queue" => Ok(Backend::Queue),
+            "sequential" => Ok(Backend::Sequential),
+            _ => Err(format!(
+                "Unknown concurrency backend: {} (expected rayon, scoped, queue or sequential)",
+                s
+            )),
+        }
+    }
+}
+
+/// The number of threads to use when `n_threads` are requested, where 0 stands for the
+/// available parallelism
+pub fn resolve_n_threads(n_threads: usize) -> usize {
+    match n_threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n_threads => n_threads,
+    }
+}
+
+/// Number of chunks of `chunk_len` bytes in a task of at least `min_task_len` bytes
+fn min_chunks_per_task(min_task_len: usize, chunk_len: usize) -> usize {
+    min_task_len.div_ceil(chunk_len).max(1)
+}
+
+/// Call `f` for each chunk of `part`, whose first chunk has index `first_index`
+fn process_part(
+    part: &mut [u8],
+    first_index: usize,
+    chunk_len: usize,
+    f: &(dyn Fn(usize, &mut [u8]) + Sync),
+) {
+    part.chunks_mut(chunk_len)
+        .enumerate()
+        .for_each(|(i, chunk)| f(first_index + i, chunk));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    const BACKENDS: [Backend; 4] = [
+        Backend::Rayon,
+        Backend::Scoped,
+        Backend::Queue,
+        Backend::Sequential,
+    ];
+
+    #[test]
+    fn test_every_chunk_is_processed_once() {
+        for backend in BACKENDS {
+            for (n_threads, task_blocks) in [(1, 1), (3, 1), (3, 4)] {
+                let executor = backend.executor(n_threads, task_blocks).unwrap();
+                for len in [0, 1, 16, 100, 1000] {
+                    let mut data = vec![0u8; len];
+                    let calls = AtomicUsize::new(0);
+
+                    executor.for_each_chunk(&mut data, 7, &|i, chunk| {
+                        calls.fetch_add(1, Ordering::Relaxed);
+                        assert!(chunk.len() == 7 || (i == len / 7 && chunk.len() == len % 7));
+                        chunk.iter_mut().for_each(|byte| *byte += i as u8 + 1);
+                    });
+
+                    assert_eq!(calls.into_inner(), len.div_ceil(7), "{:?}", backend);
+                    let expected: Vec<u8> = (0..len).map(|i| (i / 7) as u8 + 1).collect();
+                    assert_eq!(data, expected, "{:?} with {} threads", backend, n_threads);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_for_each_block_chunk() {
+        for backend in BACKENDS {
+            let executor = backend.executor(2, 1).unwrap();
+            let mut blocks = vec![[0u8; 16]; 33];
+
+            executor.for_each_block_chunk(&mut blocks, 4, |first_index, chunk| {
+                assert!(chunk.len() == 4 || (first_index == 32 && chunk.len() == 1));
+                for (i, block) in chunk.iter_mut().enumerate() {
+                    block.fill((first_index + i) as u8);
+                }
+            });
+
+            assert!(blocks
+                .iter()
+                .enumerate()
+                .all(|(i, block)| *block == [i as u8; 16]));
+        }
+    }
+
+    #[test]
+    fn test_tasks_have_the_minimum_length() {
+        for backend in BACKENDS {
+            // A single task holds all the data, so it is processed on one thread
+            let executor = backend.executor(3, 64).unwrap();
+            let mut data = vec![0u8; 1000];
+            let threads = Mutex::new(HashSet::new());
+
+            executor.for_each_chunk(&mut data, 7, &|_, _| {
+                threads.lock().unwrap().insert(std::thread::current().id());
+            });
+
+            assert_eq!(threads.into_inner().unwrap().len(), 1, "{:?}", backend);
+        }
+    }
+
+    #[test]
+    fn test_parse_backend() {
+        for backend in BACKENDS {
+            assert_eq!(backend.name().parse::<Backend>(), Ok(backend));
+        }
+        assert_eq!("Rayon".parse::<Backend>(), Ok(Backend::Rayon));
+        assert!("tokio".parse::<Backend>().is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use super::{min_chunks_per_task, process_part, Executor};
+use std::sync::{mpsc, Mutex};
+
+/// Number of tasks the data is split into for each thread, so that the threads that finish
+/// early take more of them, unless that makes them shorter than the minimum task length
+const TASKS_PER_THREAD: usize = 4;
+
+/// Splits the data into tasks that are sent to a `std::sync::mpsc` channel, from which the
+/// threads spawned for each call take them until it is empty. The current thread works on the
+/// tasks as well.
+pub struct QueueExecutor {
+    n_threads: usize,
+    min_task_len: usize,
+}
+
+impl QueueExecutor {
+    pub fn new(n_threads: usize, min_task_len: usize) -> Self {
+        QueueExecutor {
+            n_threads: n_threads.max(1),
+            min_task_len,
+        }
+    }
+}
+
+impl Executor for QueueExecutor {
+    fn for_each_chunk(
+        &self,
+        data: &mut [u8],
+        chunk_len: usize,
+        f: &(dyn Fn(usize, &mut [u8]) + Sync),
+    ) {
+        let n_chunks = data.len().div_ceil(chunk_len);
+        let chunks_per_task = n_chunks
+            .div_ceil(TASKS_PER_THREAD * self.n_threads)
+            .max(min_chunks_per_task(self.min_task_len, chunk_len));
+
+        let (sender, receiver) = mpsc::channel();
+        for (task_index, task) in data.chunks_mut(chunks_per_task * chunk_len).enumerate() {
+            sender
+                .send((task_index * chunks_per_task, task))
+                .expect("the receiver is alive");
+        }
+        // Closing the channel lets the workers stop once it is empty
+        drop(sender);
+
+        let receiver = Mutex::new(receiver);
+        let worker = || loop {
+            // The lock is released as soon as a task has been taken, not after processing it
+            let task = receiver.lock().unwrap().recv();
+            let Ok((first_index, task)) = task else {
+                break;
+            };
+            process_part(task, first_index, chunk_len, f);
+        };
+
+        std::thread::scope(|scope| {
+            for _ in 1..self.n_threads {
+                scope.spawn(worker);
+            }
+            worker();
+        });
+    }
+}
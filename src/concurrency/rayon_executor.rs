@@ -0,0 +1,41 @@
+use super::{min_chunks_per_task, Executor};
+use crate::{Error, Result};
+use rayon::prelude::*;
+
+/// Processes the chunks on a rayon thread pool, which balances the work between its threads
+/// by work stealing. Rayon splits the chunks into tasks down to a single chunk, unless a
+/// minimum task length is given, which is passed to `with_min_len`.
+pub struct RayonExecutor {
+    thread_pool: rayon::ThreadPool,
+    min_task_len: usize,
+}
+
+impl RayonExecutor {
+    pub fn new(n_threads: usize, min_task_len: usize) -> Result<Self> {
+        let thread_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(n_threads)
+            .build()
+            .map_err(|e| Error::InvalidConfig(format!("cannot create the thread pool: {}", e)))?;
+        Ok(RayonExecutor {
+            thread_pool,
+            min_task_len,
+        })
+    }
+}
+
+impl Executor for RayonExecutor {
+    fn for_each_chunk(
+        &self,
+        data: &mut [u8],
+        chunk_len: usize,
+        f: &(dyn Fn(usize, &mut [u8]) + Sync),
+    ) {
+        let min_chunks = min_chunks_per_task(self.min_task_len, chunk_len);
+        self.thread_pool.install(|| {
+            data.par_chunks_mut(chunk_len)
+                .with_min_len(min_chunks)
+                .enumerate()
+                .for_each(|(i, chunk)| f(i, chunk))
+        });
+    }
+}
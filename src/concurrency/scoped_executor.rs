@@ -0,0 +1,45 @@
+use super::{min_chunks_per_task, process_part, Executor};
+
+/// Splits the data in one contiguous part per thread, and spawns scoped threads for each call.
+/// The current thread processes the first part. Parts are never shorter than the minimum task
+/// length, so small batches are split between fewer threads.
+pub struct ScopedExecutor {
+    n_threads: usize,
+    min_task_len: usize,
+}
+
+impl ScopedExecutor {
+    pub fn new(n_threads: usize, min_task_len: usize) -> Self {
+        ScopedExecutor {
+            n_threads: n_threads.max(1),
+            min_task_len,
+        }
+    }
+}
+
+impl Executor for ScopedExecutor {
+    fn for_each_chunk(
+        &self,
+        data: &mut [u8],
+        chunk_len: usize,
+        f: &(dyn Fn(usize, &mut [u8]) + Sync),
+    ) {
+        let n_chunks = data.len().div_ceil(chunk_len);
+        let chunks_per_thread = n_chunks
+            .div_ceil(self.n_threads)
+            .max(min_chunks_per_task(self.min_task_len, chunk_len));
+
+        std::thread::scope(|scope| {
+            let mut parts = data.chunks_mut(chunks_per_thread * chunk_len).enumerate();
+            let first_part = parts.next();
+            for (part_index, part) in parts {
+                scope.spawn(move || {
+                    process_part(part, part_index * chunks_per_thread, chunk_len, f)
+                });
+            }
+            if let Some((_, part)) = first_part {
+                process_part(part, 0, chunk_len, f);
+            }
+        });
+    }
+}
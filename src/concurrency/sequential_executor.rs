@@ -0,0 +1,15 @@
+use super::{process_part, Executor};
+
+/// Processes every chunk on the current thread, in order
+pub struct SequentialExecutor;
+
+impl Executor for SequentialExecutor {
+    fn for_each_chunk(
+        &self,
+        data: &mut [u8],
+        chunk_len: usize,
+        f: &(dyn Fn(usize, &mut [u8]) + Sync),
+    ) {
+        process_part(data, 0, chunk_len, f);
+    }
+}
@@ -1,36 +1,243 @@
+use aes_rust::aes_block_cipher::{Implementation, KeySize};
+use aes_rust::aes_cipher::DEFAULT_BUFFER_BLOCKS;
+use aes_rust::concurrency::Backend;
+use aes_rust::kdf;
+use aes_rust::key::{self, KeySource};
+use aes_rust::metrics_logger::MetricsBackend;
+use aes_rust::modes::Mode;
+use aes_rust::padding::PaddingScheme;
+use std::env::VarError;
+
 #[derive(Debug)]
 pub struct Config {
     pub n_threads: usize,
+    pub backend: Backend,
+    pub task_blocks: usize,
+    pub buffer_blocks: usize,
+    pub implementation: Implementation,
     pub input_file: Option<String>,
     pub encrypted_file: Option<String>,
     pub decrypted_file: Option<String>,
     pub repeat: usize,
-    pub publish_metrics: bool
+    pub warmup: usize,
+    pub sweep_threads: Option<Vec<usize>>,
+    pub publish_metrics: bool,
+    pub metrics_backends: Vec<MetricsBackend>,
+    pub statsd_host: String,
+    pub statsd_port: u16,
+    pub metrics_prefix: String,
+    pub metrics_json_file: String,
+    pub metrics_csv_file: String,
+    pub prometheus_file: String,
+    pub run_id: String,
+    pub mode: Mode,
+    pub padding: PaddingScheme,
+    pub decrypt_range: Option<(u64, u64)>,
+    pub key_source: Option<KeySource>,
+    pub keygen_file: Option<String>,
+    pub key_size: KeySize,
+    pub kdf_iterations: u32,
+    pub pipelined: bool,
+    pub mmap: bool,
+    pub positional_io: bool,
+}
+
+/// The configuration without any variable set, which does not depend on the environment
+impl Default for Config {
+    fn default() -> Self {
+        Self::new_from_vars(|_| Err(VarError::NotPresent))
+    }
 }
 
 impl Config {
     pub fn new_from_env() -> Self {
-        let n_threads = std::env::var("N_THREADS")
+        Self::new_from_vars(|name| std::env::var(name))
+    }
+
+    /// Read the configuration from variables looked up with `var`, falling back to the default
+    /// of each one that is not set
+    fn new_from_vars<F>(var: F) -> Self
+    where
+        F: Fn(&str) -> Result<String, VarError>,
+    {
+        let n_threads = var("N_THREADS")
             .unwrap_or("1".to_string())
             .parse()
             .expect("Error while parsing N_THREADS");
+        let backend = var("BACKEND")
+            .unwrap_or("rayon".to_string())
+            .parse()
+            .expect("Error while parsing BACKEND");
+        let task_blocks = var("TASK_BLOCKS")
+            .unwrap_or("1".to_string())
+            .parse()
+            .expect("Error while parsing TASK_BLOCKS");
+        let buffer_blocks = var("BUFFER_BLOCKS")
+            .map(|blocks| blocks.parse().expect("Error while parsing BUFFER_BLOCKS"))
+            .unwrap_or(DEFAULT_BUFFER_BLOCKS);
+        let implementation = var("AES_IMPLEMENTATION")
+            .unwrap_or("auto".to_string())
+            .parse()
+            .expect("Error while parsing AES_IMPLEMENTATION");
 
-        let input_file = std::env::var("PLAIN_TEXT").ok();
-        let encrypted_file = std::env::var("ENCRYPTED_TEXT").ok();
-        let decrypted_file = std::env::var("DECRYPTED_TEXT").ok();
-        let repeat = std::env::var("REPEAT")
+        let input_file = var("PLAIN_TEXT").ok();
+        let encrypted_file = var("ENCRYPTED_TEXT").ok();
+        let decrypted_file = var("DECRYPTED_TEXT").ok();
+        let repeat = var("REPEAT")
             .unwrap_or("1".to_string())
             .parse()
             .expect("Error while parsing REPEAT");
-        let publish_metrics = std::env::var("LOCAL").unwrap_or("false".to_string()).as_str() == "true";
+        let warmup = var("WARMUP")
+            .unwrap_or("0".to_string())
+            .parse()
+            .expect("Error while parsing WARMUP");
+        let sweep_threads = var("SWEEP_THREADS").ok().map(|counts| {
+            Self::parse_thread_counts(&counts).expect("Error while parsing SWEEP_THREADS")
+        });
+        // LOCAL is the name the variable had before, which is still accepted
+        let publish_metrics = var("PUBLISH_METRICS")
+            .or_else(|_| var("LOCAL"))
+            .unwrap_or("false".to_string())
+            .as_str()
+            == "true";
+        let metrics_backends = MetricsBackend::parse_list(
+            &var("METRICS_BACKENDS").unwrap_or("statsd".to_string()),
+        )
+        .expect("Error while parsing METRICS_BACKENDS");
+        let statsd_host = var("STATSD_HOST").unwrap_or("graphite".to_string());
+        let statsd_port = var("STATSD_PORT")
+            .unwrap_or("8125".to_string())
+            .parse()
+            .expect("Error while parsing STATSD_PORT");
+        // STATSD_PREFIX is the name the variable had when StatsD was the only backend
+        let metrics_prefix = var("METRICS_PREFIX")
+            .or_else(|_| var("STATSD_PREFIX"))
+            .unwrap_or("aes_cipher".to_string());
+        let metrics_json_file = var("METRICS_JSON_FILE").unwrap_or("metrics.jsonl".to_string());
+        let metrics_csv_file = var("METRICS_CSV_FILE").unwrap_or("metrics.csv".to_string());
+        let prometheus_file = var("PROMETHEUS_FILE").unwrap_or("metrics.prom".to_string());
+        let run_id = var("RUN_ID")
+            .unwrap_or_else(|_| key::to_hex(&rand::random::<[u8; 4]>()));
+        let mode = var("MODE")
+            .unwrap_or("ecb".to_string())
+            .parse()
+            .expect("Error while parsing MODE");
+        let padding = var("PADDING")
+            .unwrap_or("pkcs7".to_string())
+            .parse()
+            .expect("Error while parsing PADDING");
+        let decrypt_range = var("DECRYPT_RANGE")
+            .ok()
+            .map(|range| Self::parse_range(&range).expect("Error while parsing DECRYPT_RANGE"));
+        let key_source = Self::key_source_from_vars(&var);
+        let keygen_file = var("KEYGEN").ok();
+        let key_size = var("KEY_SIZE")
+            .unwrap_or("128".to_string())
+            .parse()
+            .expect("Error while parsing KEY_SIZE");
+        let kdf_iterations = var("KDF_ITERATIONS")
+            .map(|iterations| iterations.parse().expect("Error while parsing KDF_ITERATIONS"))
+            .unwrap_or(kdf::DEFAULT_ITERATIONS);
+        let pipelined = var("PIPELINED").unwrap_or("false".to_string()).as_str() == "true";
+        let mmap = var("MMAP").unwrap_or("false".to_string()).as_str() == "true";
+        let positional_io = var("POSITIONAL_IO").unwrap_or("false".to_string()).as_str() == "true";
 
         Config {
             n_threads,
+            backend,
+            task_blocks,
+            buffer_blocks,
+            implementation,
             input_file,
             encrypted_file,
             decrypted_file,
             repeat,
-            publish_metrics
+            warmup,
+            sweep_threads,
+            publish_metrics,
+            metrics_backends,
+            statsd_host,
+            statsd_port,
+            metrics_prefix,
+            metrics_json_file,
+            metrics_csv_file,
+            prometheus_file,
+            run_id,
+            mode,
+            padding,
+            decrypt_range,
+            key_source,
+            keygen_file,
+            key_size,
+            kdf_iterations,
+            pipelined,
+            mmap,
+            positional_io,
+        }
+    }
+
+    /// The key is read from `KEY`, `KEY_FILE`, derived from `PASSPHRASE` or read from a prompt
+    /// if `KEY_PROMPT` is set, in that order of precedence
+    fn key_source_from_vars<F>(var: &F) -> Option<KeySource>
+    where
+        F: Fn(&str) -> Result<String, VarError>,
+    {
+        if let Ok(hex) = var("KEY") {
+            return Some(KeySource::Hex(hex));
+        }
+        if let Ok(path) = var("KEY_FILE") {
+            return Some(KeySource::File(path));
+        }
+        if let Ok(passphrase) = var("PASSPHRASE") {
+            return Some(KeySource::Passphrase(passphrase));
+        }
+        match var("KEY_PROMPT").unwrap_or("false".to_string()).as_str() {
+            "true" => Some(KeySource::Prompt),
+            _ => None,
+        }
+    }
+
+    /// Parse a byte range with the format `<start>:<length>`
+    pub fn parse_range(range: &str) -> Result<(u64, u64), String> {
+        let (start, len) = range
+            .split_once(':')
+            .ok_or(format!("Invalid range: {}", range))?;
+        let start = start
+            .trim()
+            .parse()
+            .map_err(|e| format!("Invalid range start: {}", e))?;
+        let len = len
+            .trim()
+            .parse()
+            .map_err(|e| format!("Invalid range length: {}", e))?;
+        Ok((start, len))
+    }
+
+    /// Parse a comma-separated list of thread counts and ranges of them, such as `1,2,4,8`,
+    /// `1..=16` or `1..4,8`, keeping their order
+    pub fn parse_thread_counts(counts: &str) -> Result<Vec<usize>, String> {
+        let parse = |count: &str| {
+            count
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid thread count: {}", count))
+        };
+
+        let mut thread_counts = Vec::new();
+        for item in counts.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let range = match (item.split_once("..="), item.split_once("..")) {
+                (Some((start, end)), _) => parse(start)?..=parse(end)?,
+                (None, Some((start, end))) => parse(start)?..=parse(end)?.saturating_sub(1),
+                (None, None) => parse(item)?..=parse(item)?,
+            };
+            if range.is_empty() {
+                return Err(format!("Empty range of thread counts: {}", item));
+            }
+            thread_counts.extend(range);
+        }
+        if thread_counts.is_empty() || thread_counts.contains(&0) {
+            return Err(format!("Invalid thread counts: {}, which must be at least 1", counts));
         }
+        Ok(thread_counts)
     }
 }
\ No newline at end of file
@@ -0,0 +1,375 @@
+/*
+Header written before the ciphertext, so a file can be deciphered without knowing the settings
+it was ciphered with. All integers are big-endian.
+
+    magic       4 bytes     "AESR"
+    version     1 byte
+    key size    1 byte      1 = AES-128, 2 = AES-192, 3 = AES-256
+    mode        1 byte      1 = ECB, 2 = CBC, 3 = CTR (128-bit counter), 4 = CTR (64-bit counter),
+                            5 = CTR (32-bit counter), 6 = GCM
+    padding     1 byte      0 = none, 1 = PKCS#7, 2 = ISO/IEC 7816-4, 3 = ANSI X9.23, 4 = zeros
+    IV length   1 byte
+    IV          IV length bytes
+    KDF         1 byte      0 = none (raw key), 1 = PBKDF2-HMAC-SHA256
+    salt length 1 byte
+    salt        salt length bytes
+    iterations  4 bytes
+    tag length  1 byte      length of the authentication tag appended after the ciphertext
+
+In GCM mode the whole header is authenticated as associated data.
+ */
+use crate::aes_block_cipher::KeySize;
+use crate::kdf::{self, KdfAlgorithm, KdfParams};
+use crate::modes::ctr::CounterLayout;
+use crate::modes::{gcm, Mode};
+use crate::padding::PaddingScheme;
+use crate::{Error, Result};
+use std::io::{Read, Write};
+
+pub const MAGIC: [u8; 4] = *b"AESR";
+pub const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub key_size: KeySize,
+    pub mode: Mode,
+    pub padding: PaddingScheme,
+    pub iv: Vec<u8>,
+    pub kdf: Option<KdfParams>,
+    pub tag_len: usize,
+}
+
+impl Header {
+    /// Creates the header for a file ciphered with the given settings. The padding is only
+    /// recorded for the modes that use it.
+    pub fn new(
+        key_size: KeySize,
+        mode: Mode,
+        padding: PaddingScheme,
+        iv: Vec<u8>,
+        kdf: Option<KdfParams>,
+    ) -> Self {
+        let padding = match mode.needs_padding() {
+            true => padding,
+            false => PaddingScheme::None,
+        };
+        let tag_len = match mode {
+            Mode::Gcm => gcm::TAG_LEN,
+            _ => 0,
+        };
+        Self {
+            key_size,
+            mode,
+            padding,
+            iv,
+            kdf,
+            tag_len,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(64);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.push(key_size_id(self.key_size));
+        bytes.push(mode_id(self.mode));
+        bytes.push(padding_id(self.padding));
+        bytes.push(self.iv.len() as u8);
+        bytes.extend_from_slice(&self.iv);
+        match &self.kdf {
+            Some(kdf) => {
+                bytes.push(kdf_id(kdf.algorithm));
+                bytes.push(kdf.salt.len() as u8);
+                bytes.extend_from_slice(&kdf.salt);
+                bytes.extend_from_slice(&kdf.iterations.to_be_bytes());
+            }
+            None => {
+                bytes.push(0);
+                bytes.push(0);
+                bytes.extend_from_slice(&0u32.to_be_bytes());
+            }
+        }
+        bytes.push(self.tag_len as u8);
+        bytes
+    }
+
+    pub fn write_to<W: Write>(&self, output: &mut W) -> std::io::Result<()> {
+        output.write_all(&self.to_bytes())
+    }
+
+    /// Read and validate the header at the start of the input, leaving the input positioned
+    /// at the start of the ciphertext.
+    pub fn read_from<R: Read>(input: &mut R) -> Result<Self> {
+        let mut fixed = [0; 9];
+        input.read_exact(&mut fixed).map_err(truncated)?;
+        if fixed[..4] != MAGIC {
+            return Err(invalid(
+                "not an encrypted file, the magic number is wrong".to_string(),
+            ));
+        }
+        if fixed[4] != VERSION {
+            return Err(invalid(format!(
+                "unsupported container version {} (expected {})",
+                fixed[4], VERSION
+            )));
+        }
+        let key_size = key_size_from_id(fixed[5])?;
+        let mode = mode_from_id(fixed[6])?;
+        let padding = padding_from_id(fixed[7])?;
+        let iv = read_vec(input, fixed[8] as usize)?;
+
+        let mut kdf_fields = [0; 2];
+        input.read_exact(&mut kdf_fields).map_err(truncated)?;
+        let salt = read_vec(input, kdf_fields[1] as usize)?;
+        let mut iterations = [0; 4];
+        input.read_exact(&mut iterations).map_err(truncated)?;
+        let kdf = kdf_from_id(kdf_fields[0])?.map(|algorithm| KdfParams {
+            algorithm,
+            salt,
+            iterations: u32::from_be_bytes(iterations),
+        });
+
+        let mut tag_len = [0; 1];
+        input.read_exact(&mut tag_len).map_err(truncated)?;
+
+        let header = Self {
+            key_size,
+            mode,
+            padding,
+            iv,
+            kdf,
+            tag_len: tag_len[0] as usize,
+        };
+        header.validate()?;
+        Ok(header)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let expected_iv_len = match self.mode {
+            Mode::Ecb => Some(0),
+            Mode::Cbc | Mode::Ctr(_) => Some(16),
+            Mode::Gcm => None,
+        };
+        let iv_len_is_valid = match expected_iv_len {
+            Some(len) => self.iv.len() == len,
+            None => !self.iv.is_empty(),
+        };
+        if !iv_len_is_valid {
+            return Err(invalid(format!(
+                "wrong IV length for {:?} mode: {} bytes",
+                self.mode,
+                self.iv.len()
+            )));
+        }
+
+        if let Some(params) = &self.kdf {
+            if params.iterations == 0 || params.iterations > kdf::MAX_ITERATIONS {
+                return Err(invalid(format!(
+                    "the KDF iteration count {} is not between 1 and {}",
+                    params.iterations,
+                    kdf::MAX_ITERATIONS
+                )));
+            }
+        }
+
+        let expected_tag_len = match self.mode {
+            Mode::Gcm => gcm::TAG_LEN,
+            _ => 0,
+        };
+        if self.tag_len != expected_tag_len {
+            return Err(invalid(format!(
+                "wrong tag length for {:?} mode: {} bytes",
+                self.mode, self.tag_len
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn read_vec<R: Read>(input: &mut R, len: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    input.read_exact(&mut bytes).map_err(truncated)?;
+    Ok(bytes)
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidHeader(message)
+}
+
+fn truncated(error: std::io::Error) -> Error {
+    match error.kind() {
+        std::io::ErrorKind::UnexpectedEof => {
+            invalid("not an encrypted file, the header is truncated".to_string())
+        }
+        _ => Error::Io(error),
+    }
+}
+
+fn key_size_id(key_size: KeySize) -> u8 {
+    match key_size {
+        KeySize::Aes128 => 1,
+        KeySize::Aes192 => 2,
+        KeySize::Aes256 => 3,
+    }
+}
+
+fn key_size_from_id(id: u8) -> Result<KeySize> {
+    match id {
+        1 => Ok(KeySize::Aes128),
+        2 => Ok(KeySize::Aes192),
+        3 => Ok(KeySize::Aes256),
+        _ => Err(invalid(format!("unknown key size id {}", id))),
+    }
+}
+
+fn mode_id(mode: Mode) -> u8 {
+    match mode {
+        Mode::Ecb => 1,
+        Mode::Cbc => 2,
+        Mode::Ctr(CounterLayout::Ctr128) => 3,
+        Mode::Ctr(CounterLayout::Ctr64) => 4,
+        Mode::Ctr(CounterLayout::Ctr32) => 5,
+        Mode::Gcm => 6,
+    }
+}
+
+fn mode_from_id(id: u8) -> Result<Mode> {
+    match id {
+        1 => Ok(Mode::Ecb),
+        2 => Ok(Mode::Cbc),
+        3 => Ok(Mode::Ctr(CounterLayout::Ctr128)),
+        4 => Ok(Mode::Ctr(CounterLayout::Ctr64)),
+        5 => Ok(Mode::Ctr(CounterLayout::Ctr32)),
+        6 => Ok(Mode::Gcm),
+        _ => Err(invalid(format!("unknown mode id {}", id))),
+    }
+}
+
+fn padding_id(padding: PaddingScheme) -> u8 {
+    match padding {
+        PaddingScheme::None => 0,
+        PaddingScheme::Pkcs7 => 1,
+        PaddingScheme::Iso7816 => 2,
+        PaddingScheme::AnsiX923 => 3,
+        PaddingScheme::Zero => 4,
+    }
+}
+
+fn padding_from_id(id: u8) -> Result<PaddingScheme> {
+    match id {
+        0 => Ok(PaddingScheme::None),
+        1 => Ok(PaddingScheme::Pkcs7),
+        2 => Ok(PaddingScheme::Iso7816),
+        3 => Ok(PaddingScheme::AnsiX923),
+        4 => Ok(PaddingScheme::Zero),
+        _ => Err(invalid(format!("unknown padding id {}", id))),
+    }
+}
+
+fn kdf_id(algorithm: KdfAlgorithm) -> u8 {
+    match algorithm {
+        KdfAlgorithm::Pbkdf2HmacSha256 => 1,
+    }
+}
+
+fn kdf_from_id(id: u8) -> Result<Option<KdfAlgorithm>> {
+    match id {
+        0 => Ok(None),
+        1 => Ok(Some(KdfAlgorithm::Pbkdf2HmacSha256)),
+        _ => Err(invalid(format!("unknown KDF id {}", id))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn gcm_header() -> Header {
+        let kdf = KdfParams {
+            algorithm: KdfAlgorithm::Pbkdf2HmacSha256,
+            salt: vec![7; 16],
+            iterations: 100_000,
+        };
+        Header::new(
+            KeySize::Aes256,
+            Mode::Gcm,
+            PaddingScheme::Pkcs7,
+            vec![1; gcm::IV_LEN],
+            Some(kdf),
+        )
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let header = gcm_header();
+        let mut bytes = header.to_bytes();
+        bytes.extend_from_slice(b"ciphertext");
+
+        let mut input = Cursor::new(&bytes);
+        let read_header = Header::read_from(&mut input).unwrap();
+
+        assert_eq!(read_header, header);
+        assert_eq!(read_header.padding, PaddingScheme::None);
+        assert_eq!(read_header.tag_len, gcm::TAG_LEN);
+        assert_eq!(input.position() as usize, bytes.len() - b"ciphertext".len());
+    }
+
+    #[test]
+    fn test_header_without_kdf() {
+        let header = Header::new(
+            KeySize::Aes128,
+            Mode::Cbc,
+            PaddingScheme::Iso7816,
+            vec![2; 16],
+            None,
+        );
+        let bytes = header.to_bytes();
+
+        assert_eq!(bytes.len(), 16 + 16);
+        assert_eq!(Header::read_from(&mut bytes.as_slice()).unwrap(), header);
+    }
+
+    #[test]
+    fn test_invalid_headers() {
+        let bytes = gcm_header().to_bytes();
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 2;
+        let mut wrong_iv_len = bytes.clone();
+        wrong_iv_len[6] = mode_id(Mode::Cbc);
+
+        for bytes in [
+            wrong_magic,
+            wrong_version,
+            wrong_iv_len,
+            bytes[..20].to_vec(),
+        ] {
+            let error = Header::read_from(&mut bytes.as_slice()).unwrap_err();
+            assert!(matches!(error, Error::InvalidHeader(_)));
+        }
+    }
+
+    #[test]
+    fn test_kdf_iteration_count_is_bounded() {
+        for (iterations, is_valid) in [
+            (0, false),
+            (1, true),
+            (kdf::MAX_ITERATIONS, true),
+            (kdf::MAX_ITERATIONS + 1, false),
+            (u32::MAX, false),
+        ] {
+            let mut header = gcm_header();
+            header.kdf.as_mut().unwrap().iterations = iterations;
+            let result = Header::read_from(&mut header.to_bytes().as_slice());
+            match is_valid {
+                true => assert_eq!(result.unwrap(), header),
+                false => assert!(matches!(result, Err(Error::InvalidHeader(_)))),
+            }
+        }
+
+        assert!(KdfParams::new_random(kdf::MAX_ITERATIONS + 1).is_err());
+    }
+}
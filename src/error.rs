@@ -0,0 +1,58 @@
+use std::fmt;
+
+/// Errors returned by the library
+#[derive(Debug)]
+pub enum Error {
+    /// The cipher key, key file or passphrase is not valid
+    InvalidKey(String),
+    /// A cipher or mode parameter is not valid, such as the number of threads
+    InvalidConfig(String),
+    /// The input is not an encrypted file, or its header is not valid or not supported
+    InvalidHeader(String),
+    /// The encrypted file was not ciphered with this kind of key, so it cannot be deciphered
+    KeyMismatch(String),
+    /// The data cannot be padded, or the deciphered data has invalid padding, which usually
+    /// means that it was deciphered with the wrong key
+    InvalidPadding(String),
+    /// The authentication tag does not match, so the ciphertext, its header or the tag were
+    /// modified, or the key is wrong
+    AuthenticationFailed,
+    /// The operation is not supported for the mode the file was ciphered with
+    Unsupported(String),
+    Io(std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidKey(message) => write!(f, "Invalid key: {}", message),
+            Error::InvalidConfig(message) => write!(f, "Invalid configuration: {}", message),
+            Error::InvalidHeader(message) => write!(f, "Invalid header: {}", message),
+            Error::KeyMismatch(message) => write!(f, "Wrong key: {}", message),
+            Error::InvalidPadding(message) => write!(f, "Invalid padding: {}", message),
+            Error::AuthenticationFailed => write!(
+                f,
+                "Authentication failed: the ciphertext or its tag were modified"
+            ),
+            Error::Unsupported(message) => write!(f, "Unsupported operation: {}", message),
+            Error::Io(error) => write!(f, "I/O error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
@@ -0,0 +1,73 @@
+/*
+HMAC-SHA256, as defined in RFC 2104 and FIPS 198-1.
+ */
+use super::sha256::{Sha256, BLOCK_LEN, DIGEST_LEN};
+
+/// HMAC keyed with a fixed key. Cloning it reuses the hashed inner and outer key pads, which
+/// PBKDF2 relies on to avoid hashing them again in every iteration.
+#[derive(Clone)]
+pub struct HmacSha256 {
+    inner: Sha256,
+    outer: Sha256,
+}
+
+impl HmacSha256 {
+    pub fn new(key: &[u8]) -> Self {
+        let mut block_key = [0; BLOCK_LEN];
+        if key.len() > BLOCK_LEN {
+            block_key[..DIGEST_LEN].copy_from_slice(&Sha256::digest(key));
+        } else {
+            block_key[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner = Sha256::new();
+        inner.update(&block_key.map(|byte| byte ^ 0x36));
+        let mut outer = Sha256::new();
+        outer.update(&block_key.map(|byte| byte ^ 0x5c));
+
+        Self { inner, outer }
+    }
+
+    /// Compute the MAC of the data in one call
+    pub fn mac(key: &[u8], data: &[u8]) -> [u8; DIGEST_LEN] {
+        let mut hmac = Self::new(key);
+        hmac.update(data);
+        hmac.finalize()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    pub fn finalize(self) -> [u8; DIGEST_LEN] {
+        let mut outer = self.outer;
+        outer.update(&self.inner.finalize());
+        outer.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::to_hex;
+
+    // RFC 4231 test cases 1, 2 and 6
+    #[test]
+    fn test_rfc_4231_vectors() {
+        assert_eq!(
+            to_hex(&HmacSha256::mac(&[0x0b; 20], b"Hi There")),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            to_hex(&HmacSha256::mac(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            to_hex(&HmacSha256::mac(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+/*
+Password-based key derivation, so that files can be ciphered with a passphrase instead of a
+raw key. The salt and iteration count are stored in the container header, so the same key can
+be derived again when deciphering.
+ */
+mod hmac;
+mod pbkdf2;
+mod sha256;
+
+pub use hmac::HmacSha256;
+pub use pbkdf2::pbkdf2_hmac_sha256;
+pub use sha256::Sha256;
+
+use crate::aes_block_cipher::KeySize;
+use crate::{Error, Result};
+
+pub const SALT_LEN: usize = 16;
+/// Iteration count recommended by OWASP for PBKDF2-HMAC-SHA256
+pub const DEFAULT_ITERATIONS: u32 = 600_000;
+/// Largest iteration count accepted, as the count read from a file header is untrusted and
+/// deriving the key runs before any authentication or padding check
+pub const MAX_ITERATIONS: u32 = 10 * DEFAULT_ITERATIONS;
+
+/// Key derivation function used to get the key from a passphrase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KdfAlgorithm {
+    Pbkdf2HmacSha256,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KdfParams {
+    pub algorithm: KdfAlgorithm,
+    pub salt: Vec<u8>,
+    pub iterations: u32,
+}
+
+impl KdfParams {
+    /// PBKDF2-HMAC-SHA256 parameters with a fresh random salt
+    pub fn new_random(iterations: u32) -> Result<Self> {
+        if iterations == 0 || iterations > MAX_ITERATIONS {
+            return Err(Error::InvalidConfig(format!(
+                "the number of KDF iterations must be between 1 and {}",
+                MAX_ITERATIONS
+            )));
+        }
+        Ok(Self {
+            algorithm: KdfAlgorithm::Pbkdf2HmacSha256,
+            salt: rand::random::<[u8; SALT_LEN]>().to_vec(),
+            iterations,
+        })
+    }
+
+    /// Derive a key of the given size from the passphrase
+    pub fn derive_key(&self, passphrase: &[u8], key_size: KeySize) -> Vec<u8> {
+        let mut key = vec![0; key_size.key_len()];
+        match self.algorithm {
+            KdfAlgorithm::Pbkdf2HmacSha256 => {
+                pbkdf2_hmac_sha256(passphrase, &self.salt, self.iterations, &mut key)
+            }
+        }
+        key
+    }
+}
@@ -0,0 +1,84 @@
+/*
+PBKDF2, as defined in RFC 8018 section 5.2, with HMAC-SHA256 as the pseudorandom function.
+ */
+use super::hmac::HmacSha256;
+use super::sha256::DIGEST_LEN;
+
+/// Fill `output` with the key derived from the password and salt
+pub fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], iterations: u32, output: &mut [u8]) {
+    let prf = HmacSha256::new(password);
+
+    for (i, output_block) in output.chunks_mut(DIGEST_LEN).enumerate() {
+        let mut hmac = prf.clone();
+        hmac.update(salt);
+        hmac.update(&(i as u32 + 1).to_be_bytes());
+        let mut u = hmac.finalize();
+        let mut t = u;
+
+        for _ in 1..iterations {
+            let mut hmac = prf.clone();
+            hmac.update(&u);
+            u = hmac.finalize();
+            t.iter_mut().zip(u.iter()).for_each(|(t, u)| *t ^= u);
+        }
+
+        output_block.copy_from_slice(&t[..output_block.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::to_hex;
+
+    fn derive(password: &[u8], salt: &[u8], iterations: u32, len: usize) -> String {
+        let mut output = vec![0; len];
+        pbkdf2_hmac_sha256(password, salt, iterations, &mut output);
+        to_hex(&output)
+    }
+
+    // RFC 6070 inputs, with the outputs for HMAC-SHA256 instead of HMAC-SHA1
+    #[test]
+    fn test_rfc_6070_style_vectors() {
+        assert_eq!(
+            derive(b"password", b"salt", 1, 32),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            derive(b"password", b"salt", 2, 32),
+            "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"
+        );
+        assert_eq!(
+            derive(b"password", b"salt", 4096, 32),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+        assert_eq!(
+            derive(
+                b"passwordPASSWORDpassword",
+                b"saltSALTsaltSALTsaltSALTsaltSALTsalt",
+                4096,
+                40
+            ),
+            "348c89dbcbd32b2f32d814b8116e84cf2b17347ebc1800181c4e2a1fb8dd53e1c635518c7dac47e9"
+        );
+        assert_eq!(
+            derive(b"pass\0word", b"sa\0lt", 4096, 16),
+            "89b69d0516f829893c696226650a8687"
+        );
+    }
+
+    // RFC 7914 section 11
+    #[test]
+    fn test_rfc_7914_vectors() {
+        assert_eq!(
+            derive(b"passwd", b"salt", 1, 64),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc\
+             49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+        );
+        assert_eq!(
+            derive(b"Password", b"NaCl", 80000, 64),
+            "4ddcd8f60b98be21830cee5ef22701f9641a4418d04c0414aeff08876b34ab56\
+             a1d425a1225833549adb841b51c9b3176a272bdebba1d078478f62b397f33c8d"
+        );
+    }
+}
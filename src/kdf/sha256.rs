@@ -0,0 +1,184 @@
+/*
+SHA-256, as defined in FIPS 180-4 section 6.2.
+ */
+
+pub const BLOCK_LEN: usize = 64;
+pub const DIGEST_LEN: usize = 32;
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const INITIAL_HASH: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+#[derive(Clone)]
+pub struct Sha256 {
+    hash: [u32; 8],
+    buffer: [u8; BLOCK_LEN],
+    buffer_len: usize,
+    message_len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Self {
+            hash: INITIAL_HASH,
+            buffer: [0; BLOCK_LEN],
+            buffer_len: 0,
+            message_len: 0,
+        }
+    }
+
+    /// Hash the data in one call
+    pub fn digest(data: &[u8]) -> [u8; DIGEST_LEN] {
+        let mut sha256 = Self::new();
+        sha256.update(data);
+        sha256.finalize()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.message_len += data.len() as u64;
+        let mut data = data;
+
+        if self.buffer_len > 0 {
+            let len = data.len().min(BLOCK_LEN - self.buffer_len);
+            self.buffer[self.buffer_len..self.buffer_len + len].copy_from_slice(&data[..len]);
+            self.buffer_len += len;
+            data = &data[len..];
+
+            if self.buffer_len < BLOCK_LEN {
+                return;
+            }
+            let block = self.buffer;
+            self.compress(&block);
+            self.buffer_len = 0;
+        }
+
+        let mut blocks = data.chunks_exact(BLOCK_LEN);
+        for block in blocks.by_ref() {
+            self.compress(block.try_into().unwrap());
+        }
+
+        let remainder = blocks.remainder();
+        self.buffer[..remainder.len()].copy_from_slice(remainder);
+        self.buffer_len = remainder.len();
+    }
+
+    /// Pad the message as described in FIPS 180-4 section 5.1.1 and return the digest
+    pub fn finalize(mut self) -> [u8; DIGEST_LEN] {
+        let message_bits = self.message_len.wrapping_mul(8);
+
+        let mut padding = [0; 2 * BLOCK_LEN];
+        padding[0] = 0x80;
+        let padding_len = match self.buffer_len < BLOCK_LEN - 8 {
+            true => BLOCK_LEN - self.buffer_len,
+            false => 2 * BLOCK_LEN - self.buffer_len,
+        };
+        padding[padding_len - 8..padding_len].copy_from_slice(&message_bits.to_be_bytes());
+        self.update(&padding[..padding_len]);
+
+        let mut digest = [0; DIGEST_LEN];
+        digest
+            .chunks_exact_mut(4)
+            .zip(self.hash.iter())
+            .for_each(|(bytes, word)| bytes.copy_from_slice(&word.to_be_bytes()));
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8; BLOCK_LEN]) {
+        let mut w = [0u32; 64];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for t in 16..64 {
+            let s0 = w[t - 15].rotate_right(7) ^ w[t - 15].rotate_right(18) ^ (w[t - 15] >> 3);
+            let s1 = w[t - 2].rotate_right(17) ^ w[t - 2].rotate_right(19) ^ (w[t - 2] >> 10);
+            w[t] = w[t - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[t - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.hash;
+        for (k, w) in K.iter().zip(w.iter()) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(*k)
+                .wrapping_add(*w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (hash, value) in self.hash.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *hash = hash.wrapping_add(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::to_hex;
+
+    #[test]
+    fn test_fips_180_examples() {
+        assert_eq!(
+            to_hex(&Sha256::digest(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            to_hex(&Sha256::digest(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            to_hex(&Sha256::digest(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_incremental_updates() {
+        // One million 'a', fed in pieces that do not line up with the blocks
+        let mut sha256 = Sha256::new();
+        let data = [b'a'; 1000];
+        for i in 0..1000 {
+            let split = i % 100;
+            sha256.update(&data[..split]);
+            sha256.update(&data[split..]);
+        }
+        assert_eq!(
+            to_hex(&sha256.finalize()),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+}
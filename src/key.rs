@@ -0,0 +1,193 @@
+/*
+Loading, validation and generation of the cipher key. Keys are given as hex strings, either
+directly, in a file or typed at a prompt, or as the raw bytes of a key file.
+ */
+use crate::aes_block_cipher::KeySize;
+use crate::{Error, Result};
+use std::io::{BufRead, Write};
+
+/// Where the cipher key is read from
+#[derive(Clone, PartialEq, Eq)]
+pub enum KeySource {
+    /// Key encoded as a hex string
+    Hex(String),
+    /// File with the key, either as a hex string or as raw bytes
+    File(String),
+    /// Hex key typed by the user on the standard input
+    Prompt,
+    /// Passphrase the key is derived from, with the salt stored in each ciphered file
+    Passphrase(String),
+}
+
+impl KeySource {
+    /// Read and validate the key, which has to be 16, 24 or 32 bytes long. A passphrase
+    /// is not a key, so it has to be given to `AESCipher::new_with_passphrase` instead.
+    pub fn load(&self) -> Result<Vec<u8>> {
+        match self {
+            KeySource::Hex(hex) => parse_hex_key(hex),
+            KeySource::File(path) => read_key_file(path),
+            KeySource::Prompt => prompt_key(),
+            KeySource::Passphrase(_) => Err(Error::InvalidKey(
+                "a passphrase has to be derived into a key with a KDF".to_string(),
+            )),
+        }
+    }
+}
+
+// The configuration is printed at startup, so the key itself is never shown
+impl std::fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySource::Hex(_) => write!(f, "Hex(<redacted>)"),
+            KeySource::File(path) => f.debug_tuple("File").field(path).finish(),
+            KeySource::Prompt => write!(f, "Prompt"),
+            KeySource::Passphrase(_) => write!(f, "Passphrase(<redacted>)"),
+        }
+    }
+}
+
+/// Parse a key from a hex string, ignoring surrounding whitespace
+pub fn parse_hex_key(hex: &str) -> Result<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) {
+        return Err(Error::InvalidKey(format!(
+            "odd number of hex digits ({})",
+            hex.len()
+        )));
+    }
+
+    let key = hex
+        .as_bytes()
+        .chunks(2)
+        .enumerate()
+        .map(|(i, pair)| match pair.iter().all(u8::is_ascii_hexdigit) {
+            // Both digits are ASCII, so the pair is valid UTF-8
+            true => Ok(u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap()),
+            false => Err(Error::InvalidKey(format!(
+                "non-hex digit at position {}",
+                2 * i
+            ))),
+        })
+        .collect::<Result<Vec<u8>>>()?;
+
+    KeySize::from_key_len(key.len())?;
+    Ok(key)
+}
+
+/// Read a key file. The file may contain the key as a hex string, or its raw bytes if they
+/// are not a valid hex key.
+pub fn read_key_file(path: &str) -> Result<Vec<u8>> {
+    let contents = std::fs::read(path)
+        .map_err(|e| Error::InvalidKey(format!("cannot read the key file {}: {}", path, e)))?;
+
+    if let Some(key) = std::str::from_utf8(&contents)
+        .ok()
+        .and_then(|hex| parse_hex_key(hex).ok())
+    {
+        return Ok(key);
+    }
+
+    KeySize::from_key_len(contents.len()).map_err(|_| {
+        Error::InvalidKey(format!(
+            "the key file {} has neither a hex key nor a raw key of 16, 24 or 32 bytes ({} bytes)",
+            path,
+            contents.len()
+        ))
+    })?;
+    Ok(contents)
+}
+
+/// Ask for a hex key on the standard input
+fn prompt_key() -> Result<Vec<u8>> {
+    eprint!("Enter the cipher key (hex): ");
+    std::io::stderr().flush().ok();
+
+    let mut line = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|e| Error::InvalidKey(format!("cannot read the key: {}", e)))?;
+    parse_hex_key(&line)
+}
+
+/// Generate a random key of the given size
+pub fn generate_key(key_size: KeySize) -> Vec<u8> {
+    let mut key = vec![0; key_size.key_len()];
+    rand::Rng::fill(&mut rand::thread_rng(), key.as_mut_slice());
+    key
+}
+
+/// Write the key to a file as a hex string, which `read_key_file` can read back
+pub fn write_key_file(path: &str, key: &[u8]) -> Result<()> {
+    std::fs::write(path, to_hex(key) + "\n")
+        .map_err(|e| Error::InvalidKey(format!("cannot write the key file {}: {}", path, e)))
+}
+
+/// Encode the bytes as a lowercase hex string
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hex_key() {
+        let key = parse_hex_key(" 2b7e151628aed2a6ABF7158809CF4F3C\n").unwrap();
+        assert_eq!(key.len(), 16);
+        assert_eq!(key[..4], [0x2b, 0x7e, 0x15, 0x16]);
+        assert_eq!(key[12..], [0x09, 0xcf, 0x4f, 0x3c]);
+
+        assert_eq!(parse_hex_key(&"00".repeat(24)).unwrap().len(), 24);
+        assert_eq!(parse_hex_key(&"ff".repeat(32)).unwrap().len(), 32);
+    }
+
+    #[test]
+    fn test_parse_invalid_hex_key() {
+        assert!(parse_hex_key("2b7e1")
+            .unwrap_err()
+            .to_string()
+            .contains("odd number"));
+        assert!(parse_hex_key(&"0g".repeat(16))
+            .unwrap_err()
+            .to_string()
+            .contains("position 0"));
+        assert!(parse_hex_key(&"00".repeat(20))
+            .unwrap_err()
+            .to_string()
+            .contains("expected 16, 24 or 32"));
+        assert!(parse_hex_key("").is_err());
+    }
+
+    #[test]
+    fn test_key_files() {
+        // Removed with the files when dropped, even if the test fails
+        let dir = tempfile::tempdir().unwrap();
+        let temp_file = |name| dir.path().join(name).to_string_lossy().into_owned();
+        let hex_file = temp_file("hex_key");
+        let key = generate_key(KeySize::Aes256);
+        write_key_file(&hex_file, &key).unwrap();
+        assert_eq!(read_key_file(&hex_file).unwrap(), key);
+
+        let raw_file = temp_file("raw_key");
+        let key = generate_key(KeySize::Aes128);
+        std::fs::write(&raw_file, &key).unwrap();
+        assert_eq!(read_key_file(&raw_file).unwrap(), key);
+
+        std::fs::write(&raw_file, [0; 20]).unwrap();
+        assert!(read_key_file(&raw_file)
+            .unwrap_err()
+            .to_string()
+            .contains("20 bytes"));
+        assert!(read_key_file(&temp_file("missing_key")).is_err());
+    }
+
+    #[test]
+    fn test_generate_key() {
+        for key_size in [KeySize::Aes128, KeySize::Aes192, KeySize::Aes256] {
+            assert_eq!(generate_key(key_size).len(), key_size.key_len());
+        }
+        assert_ne!(generate_key(KeySize::Aes128), generate_key(KeySize::Aes128));
+    }
+}
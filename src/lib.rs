@@ -0,0 +1,48 @@
+//! AES-128, AES-192 and AES-256 file encryption, with the blocks ciphered in parallel on one
+//! of several [`concurrency`] backends.
+//!
+//! The main entry point is [`AESCipher`], which ciphers any reader into a writer in ECB, CBC,
+//! CTR or GCM mode. Its output starts with a [`container::Header`] that describes the key size,
+//! mode, padding and IV, so [`AESCipher::decipher`] only needs the key to read it back. Files
+//! ciphered in GCM mode are read twice, first to authenticate them, so they are deciphered from
+//! a seekable input with [`AESCipher::decipher_seekable`].
+//!
+//! ```
+//! use aes_rust::modes::Mode;
+//! use aes_rust::{AESCipher, AESCipherConfig};
+//! use std::io::Cursor;
+//!
+//! let key = [0x2b; 32];
+//! let config = AESCipherConfig {
+//!     mode: Mode::Gcm,
+//!     n_threads: 2,
+//!     ..Default::default()
+//! };
+//! let mut cipher = AESCipher::new(&key, config)?;
+//!
+//! let mut cipher_text = Vec::new();
+//! cipher.cipher(&b"Hello World!"[..], &mut cipher_text)?;
+//!
+//! let mut plain_text = Vec::new();
+//! cipher.decipher_seekable(Cursor::new(cipher_text), &mut plain_text)?;
+//! assert_eq!(plain_text, b"Hello World!");
+//! # Ok::<(), aes_rust::Error>(())
+//! ```
+//!
+//! Keys can also be derived from a passphrase with [`AESCipher::new_with_passphrase`], and read
+//! from hex strings or key files with the [`key`] module.
+
+pub mod aes_block_cipher;
+pub mod aes_cipher;
+pub mod concurrency;
+pub mod container;
+mod error;
+pub mod kdf;
+pub mod key;
+pub mod metrics_logger;
+pub mod modes;
+pub mod padding;
+mod utils;
+
+pub use aes_cipher::{AESCipher, AESCipherConfig};
+pub use error::{Error, Result};
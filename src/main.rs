@@ -1,68 +1,478 @@
-pub mod aes_block_cipher;
-mod metrics_logger;
-mod utils;
-
+mod bench;
+mod cli;
 mod config;
 
-mod aes_cipher;
-
-use crate::aes_cipher::AESCipher;
-use crate::metrics_logger::{MetricsLogger, StatsDMetricsLogger};
+use crate::cli::{Cli, Command};
+use aes_rust::key::{self, KeySource};
+use aes_rust::aes_cipher::{Phase, PhaseTimes};
+use aes_rust::metrics_logger::{
+    FanOutMetricsLogger, FileFormat, FileMetricsLogger, MetricsBackend, MetricsLogger,
+    NoopMetricsLogger, PrometheusMetricsLogger, StatsDMetricsLogger, StdoutMetricsLogger, Tags,
+};
+use aes_rust::{AESCipher, AESCipherConfig};
+use std::io::{BufRead, Read};
 
-const BUFFER_SIZE: usize = 8192;
 const COMPLETION_TIME_METRIC_NAME: &str = "completion_time";
+const ITERATION_TIME_METRIC_NAME: &str = "iteration_time";
+const SWEEP_METRIC_NAME: &str = "sweep";
 
 fn main() -> Result<(), String> {
     dotenv::dotenv().ok();
 
-    let config = config::Config::new_from_env();
+    let mut config = config::Config::new_from_env();
+    let cli = Cli::parse(std::env::args().skip(1), &mut config)?;
+    if cli.command == Command::Help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
     println!("Starting program with the following configuration:\n{:?}", config);
 
-    let cipher_key: u128 = 0x2b7e151628aed2a6abf7158809cf4f3c;
-    let mut cipher = AESCipher::new(cipher_key, config.n_threads)?;
+    if let Some(keygen_file) = &config.keygen_file {
+        let key = key::generate_key(config.key_size);
+        key::write_key_file(keygen_file, &key).map_err(|e| e.to_string())?;
+        println!("Wrote a new {}-bit key to {}", 8 * key.len(), keygen_file);
+        return Ok(());
+    }
 
-   let start_time = std::time::Instant::now();
+    // The sweep creates a cipher for each thread count
+    if cli.command == Command::Sweep
+        || (cli.command == Command::FromEnv && config.sweep_threads.is_some())
+    {
+        return sweep(&mut config);
+    }
 
-    (0..config.repeat).for_each(|_| {
-        match run_iteration(&mut cipher, &config) {
-            Ok(_) => {}
-            Err(e) => {
-                println!("Error while encrypting/decrypting file: {}", e);
-                std::process::exit(1);
-            }
-        };
-    });
+    let mut cipher = create_cipher(&config)?;
 
-    let elapsed_time = start_time.elapsed().as_secs_f64();
-    println!("Elapsed time: {}s", elapsed_time);
+    match cli.command {
+        Command::Encrypt | Command::Decrypt => run_iteration(&mut cipher, &config)
+            .map_err(|e| format!("Error while encrypting/decrypting file: {}", e)),
+        Command::Verify => verify(&mut cipher, &config),
+        Command::Bench | Command::FromEnv => bench(&mut cipher, &config),
+        Command::Sweep | Command::Keygen | Command::Help => {
+            unreachable!("handled before creating the cipher")
+        }
+    }
+}
 
+fn create_cipher(config: &config::Config) -> Result<AESCipher, String> {
+    let key_source = config.key_source.as_ref().ok_or(
+        "No cipher key configured: set KEY, KEY_FILE, PASSPHRASE or KEY_PROMPT".to_string(),
+    )?;
+    let cipher_config = AESCipherConfig {
+        n_threads: config.n_threads,
+        backend: config.backend,
+        task_blocks: config.task_blocks,
+        implementation: config.implementation,
+        mode: config.mode,
+        padding: config.padding,
+        buffer_blocks: config.buffer_blocks,
+        pipelined: config.pipelined,
+        mmap: config.mmap,
+        positional_io: config.positional_io,
+    };
+    let cipher = match key_source {
+        KeySource::Passphrase(passphrase) => AESCipher::new_with_passphrase(
+            passphrase.as_bytes(),
+            config.key_size,
+            config.kdf_iterations,
+            cipher_config,
+        ),
+        _ => AESCipher::new(&key_source.load().map_err(|e| e.to_string())?, cipher_config),
+    };
+    cipher.map_err(|e| e.to_string())
+}
 
-    if config.publish_metrics
-    {
-        let logger: StatsDMetricsLogger = Default::default();
+/// Run the configured files through the cipher `WARMUP` times without timing them, then `REPEAT`
+/// times timing each iteration, and report the elapsed time and the statistics of the iterations
+fn bench(cipher: &mut AESCipher, config: &config::Config) -> Result<(), String> {
+    let iteration_times = time_iterations(cipher, config)?;
+    let phase_times = cipher.take_phase_times();
+
+    let elapsed_time = iteration_times.iter().sum::<f64>();
+    // The settings in use, which `auto` and 0 threads have been resolved to
+    let cipher_config = cipher.config();
+    let backend = cipher_config.backend.name();
+    let implementation = cipher_config.implementation.name();
+    let (n_threads, task_blocks) = (cipher_config.n_threads, cipher_config.task_blocks);
+    let buffer_blocks = cipher_config.buffer_blocks;
+    println!(
+        "Elapsed time with the {} backend and the {} implementation, on {} threads with tasks of at least {} blocks and a buffer of {} blocks: {}s",
+        backend, implementation, n_threads, task_blocks, buffer_blocks, elapsed_time
+    );
+    let summary = bench::Summary::new(&iteration_times, iteration_bytes(config));
+    if let Some(summary) = &summary {
+        println!("{}", summary);
+    }
+    println!(
+        "Time spent reading: {}s, ciphering: {}s, writing: {}s",
+        phase_times.read.as_secs_f64(),
+        phase_times.cipher.as_secs_f64(),
+        phase_times.write.as_secs_f64()
+    );
+
+    if config.publish_metrics {
+        let logger = create_metrics_logger(cipher, config, None)?;
+        // The settings of the run are tags of the logger, so that runs can be told apart
         logger.gauge(COMPLETION_TIME_METRIC_NAME, elapsed_time);
+        logger.gauge("n_threads", n_threads as f64);
+        logger.gauge("task_blocks", task_blocks as f64);
+        logger.gauge("buffer_blocks", buffer_blocks as f64);
+        if let Some(summary) = &summary {
+            summary.log(logger.as_ref(), ITERATION_TIME_METRIC_NAME);
+        }
+        log_phase_times(logger.as_ref(), &phase_times);
+    }
+    Ok(())
+}
+
+/// Run the configured files through the cipher `WARMUP` times, then `REPEAT` times, and return
+/// the time of each of the latter in seconds. Only these count towards the time of each phase.
+fn time_iterations(cipher: &mut AESCipher, config: &config::Config) -> Result<Vec<f64>, String> {
+    for _ in 0..config.warmup {
+        run_iteration(cipher, config)
+            .map_err(|e| format!("Error while encrypting/decrypting file: {}", e))?;
+    }
+    cipher.take_phase_times();
+
+    let mut iteration_times = Vec::with_capacity(config.repeat);
+    for _ in 0..config.repeat {
+        let start_time = std::time::Instant::now();
+        run_iteration(cipher, config)
+            .map_err(|e| format!("Error while encrypting/decrypting file: {}", e))?;
+        iteration_times.push(start_time.elapsed().as_secs_f64());
+    }
+    Ok(iteration_times)
+}
+
+/// Bench a cipher with each of the `SWEEP_THREADS` thread counts, and print a table of their
+/// speedup and parallel efficiency against a single thread, which is benched first when it is
+/// not in the list
+fn sweep(config: &mut config::Config) -> Result<(), String> {
+    let mut thread_counts = config.sweep_threads.clone().ok_or(
+        "The sweep command requires thread counts: set SWEEP_THREADS or --sweep".to_string(),
+    )?;
+    if config.repeat == 0 {
+        return Err("The sweep command requires REPEAT to be at least 1".to_string());
+    }
+    if !thread_counts.contains(&1) {
+        thread_counts.insert(0, 1);
+    }
+    // Read a prompted key once, instead of once for each cipher
+    if config.key_source == Some(KeySource::Prompt) {
+        let key = KeySource::Prompt.load().map_err(|e| e.to_string())?;
+        config.key_source = Some(KeySource::Hex(key::to_hex(&key)));
+    }
+
+    // A Prometheus file is rewritten in full, so the loggers of the thread counts share its
+    // samples to keep the series of each of them
+    let prometheus = if config.publish_metrics
+        && config.metrics_backends.contains(&MetricsBackend::Prometheus)
+    {
+        Some(create_prometheus_logger(config, Tags::default())?)
+    } else {
+        None
+    };
+
+    let mut summaries = Vec::with_capacity(thread_counts.len());
+    let mut loggers = Vec::new();
+    let mut cipher_config = None;
+    for n_threads in thread_counts {
+        config.n_threads = n_threads;
+        let mut cipher = create_cipher(config)?;
+        // The settings in use, which `auto` has been resolved to, are the same for every count
+        cipher_config = Some(*cipher.config());
+        let iteration_times = time_iterations(&mut cipher, config)?;
+        let summary = bench::Summary::new(&iteration_times, iteration_bytes(config))
+            .expect("REPEAT is at least 1");
+        println!("{} threads: mean iteration time {:.6}s", n_threads, summary.mean);
+        summaries.push((n_threads, summary));
+        if config.publish_metrics {
+            loggers.push(create_metrics_logger(&cipher, config, prometheus.as_ref())?);
+        }
+    }
+
+    let results = bench::SweepResult::compare(summaries).expect("the sweep has a single thread");
+    let cipher_config = cipher_config.expect("the sweep has a single thread");
+    println!(
+        "Sweep with the {} backend and the {} implementation, in {} mode, over {} iterations:\n{}",
+        cipher_config.backend.name(),
+        cipher_config.implementation.name(),
+        cipher_config.mode.name(),
+        config.repeat,
+        bench::sweep_table(&results)
+    );
+    // Each logger is tagged with the thread count of its result
+    for (result, logger) in results.iter().zip(&loggers) {
+        result.log(logger.as_ref(), SWEEP_METRIC_NAME);
     }
     Ok(())
 }
 
-fn run_iteration(cipher: &mut AESCipher, config: &config::Config) -> std::io::Result<()> {
+/// Report the total time of each phase as a timer and as a gauge in seconds, both named
+/// `<phase>_time`, such as `read_time`. The phases are timed inside the cipher and added up
+/// over its threads, so the totals are reported with `timer` rather than `run_and_measure`.
+fn log_phase_times(logger: &dyn MetricsLogger, phase_times: &PhaseTimes) {
+    for phase in Phase::ALL {
+        let metric = format!("{}_time", phase.name());
+        let time = phase_times.get(phase);
+        logger.timer(&metric, time);
+        logger.gauge(&metric, time.as_secs_f64());
+    }
+}
+
+/// Logger of the configured metrics backends, or a fan-out logger when there are several, whose
+/// metrics are tagged with the settings of the run so that runs with different configurations
+/// can be told apart. The Prometheus metrics go to the file of `prometheus` when given.
+fn create_metrics_logger(
+    cipher: &AESCipher,
+    config: &config::Config,
+    prometheus: Option<&PrometheusMetricsLogger>,
+) -> Result<Box<dyn MetricsLogger>, String> {
+    let cipher_config = cipher.config();
+    let tags = Tags::default()
+        .with("threads", cipher_config.n_threads)
+        .with("mode", cipher_config.mode.name())
+        .with("backend", cipher_config.backend.name())
+        .with("implementation", cipher_config.implementation.name())
+        .with("task_blocks", cipher_config.task_blocks)
+        .with("buffer_blocks", cipher_config.buffer_blocks)
+        .with("file_size", iteration_bytes(config))
+        .with("run_id", &config.run_id);
+    let prefix = config.metrics_prefix.as_str();
+    let open_error = |path: &str, e: std::io::Error| {
+        format!("Error while opening the metrics file {}: {}", path, e)
+    };
+
+    let mut loggers = config
+        .metrics_backends
+        .iter()
+        .map(|backend| -> Result<Box<dyn MetricsLogger>, String> {
+            Ok(match backend {
+                // Metrics are sent to StatsD on a best-effort basis, so a server that cannot be
+                // reached, such as the graphite container outside of its network, does not fail
+                // the run
+                MetricsBackend::StatsD => {
+                    let host = (config.statsd_host.as_str(), config.statsd_port);
+                    match StatsDMetricsLogger::new(host, prefix) {
+                        Ok(logger) => Box::new(logger.with_tags(&tags)),
+                        Err(e) => {
+                            eprintln!(
+                                "Warning: the metrics are not sent to StatsD at {}:{}: {}",
+                                config.statsd_host, config.statsd_port, e
+                            );
+                            Box::new(NoopMetricsLogger)
+                        }
+                    }
+                }
+                MetricsBackend::Stdout => Box::new(StdoutMetricsLogger::new(prefix, tags.clone())),
+                MetricsBackend::JsonLines => {
+                    let path = &config.metrics_json_file;
+                    let format = FileFormat::JsonLines;
+                    Box::new(
+                        FileMetricsLogger::new(path, format, prefix, tags.clone())
+                            .map_err(|e| open_error(path, e))?,
+                    )
+                }
+                MetricsBackend::Csv => {
+                    let path = &config.metrics_csv_file;
+                    Box::new(
+                        FileMetricsLogger::new(path, FileFormat::Csv, prefix, tags.clone())
+                            .map_err(|e| open_error(path, e))?,
+                    )
+                }
+                MetricsBackend::Prometheus => match prometheus {
+                    Some(prometheus) => Box::new(prometheus.with_tags(tags.clone())),
+                    None => Box::new(create_prometheus_logger(config, tags.clone())?),
+                },
+                MetricsBackend::Noop => Box::new(NoopMetricsLogger),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(match loggers.len() {
+        0 => Box::new(NoopMetricsLogger),
+        1 => loggers.remove(0),
+        _ => Box::new(FanOutMetricsLogger::new(loggers)),
+    })
+}
+
+fn create_prometheus_logger(
+    config: &config::Config,
+    tags: Tags,
+) -> Result<PrometheusMetricsLogger, String> {
+    let path = &config.prometheus_file;
+    PrometheusMetricsLogger::new(path, &config.metrics_prefix, tags)
+        .map_err(|e| format!("Error while opening the metrics file {}: {}", path, e))
+}
+
+/// Size of the file each iteration starts from, which is the plaintext unless only deciphering
+fn iteration_bytes(config: &config::Config) -> u64 {
+    config
+        .input_file
+        .as_ref()
+        .or(config.encrypted_file.as_ref())
+        .and_then(|file| std::fs::metadata(file).ok())
+        .map_or(0, |metadata| metadata.len())
+}
+
+/// Encrypt and decrypt the input file, and check that the decrypted file matches it
+fn verify(cipher: &mut AESCipher, config: &config::Config) -> Result<(), String> {
+    run_iteration(cipher, config)
+        .map_err(|e| format!("Error while encrypting/decrypting file: {}", e))?;
+
+    let (Some(input_file), Some(decrypted_file)) = (&config.input_file, &config.decrypted_file)
+    else {
+        return Err("Verification requires an input and a decrypted file".to_string());
+    };
+    match files_match(input_file, decrypted_file) {
+        Ok(true) => {
+            println!("Verification succeeded: {} round trips unchanged", input_file);
+            Ok(())
+        }
+        Ok(false) => Err(format!(
+            "Verification failed: the decrypted file does not match {}",
+            input_file
+        )),
+        Err(e) => Err(format!("Error while comparing the files: {}", e)),
+    }
+}
+
+fn files_match(file: &str, other_file: &str) -> std::io::Result<bool> {
+    let file = std::fs::File::open(file)?;
+    let other_file = std::fs::File::open(other_file)?;
+    if file.metadata()?.len() != other_file.metadata()?.len() {
+        return Ok(false);
+    }
+
+    let mut reader = std::io::BufReader::new(file);
+    let mut other_reader = std::io::BufReader::new(other_file);
+    let mut other_bytes = Vec::new();
+    loop {
+        let bytes = reader.fill_buf()?;
+        if bytes.is_empty() {
+            return Ok(true);
+        }
+
+        other_bytes.resize(bytes.len(), 0);
+        other_reader.read_exact(&mut other_bytes)?;
+        if bytes != other_bytes.as_slice() {
+            return Ok(false);
+        }
+
+        let len = bytes.len();
+        reader.consume(len);
+    }
+}
+
+fn run_iteration(cipher: &mut AESCipher, config: &config::Config) -> aes_rust::Result<()> {
     match (&config.input_file, &config.encrypted_file, &config.decrypted_file) {
         (Some(input_file), Some(encrypted_file), Some(decrypted_file)) => {
             cipher.cipher_file(input_file.as_str(), encrypted_file.as_str())?;
-            cipher.decipher_file(encrypted_file.as_str(), decrypted_file.as_str())?;
+            decipher_file(cipher, config, encrypted_file, decrypted_file)?;
         }
         (Some(input_file), Some(encrypted_file), None) => {
             cipher.cipher_file(input_file.as_str(), encrypted_file.as_str())?;
         }
         (None, Some(encrypted_file), Some(decrypted_file)) => {
-            cipher.decipher_file(encrypted_file.as_str(), decrypted_file.as_str())?;
+            decipher_file(cipher, config, encrypted_file, decrypted_file)?;
         }
         _ => {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "Invalid input",
+            return Err(aes_rust::Error::InvalidConfig(
+                "set PLAIN_TEXT and ENCRYPTED_TEXT to encrypt, or ENCRYPTED_TEXT and \
+                 DECRYPTED_TEXT to decrypt"
+                    .to_string(),
             ));
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+fn decipher_file(
+    cipher: &mut AESCipher,
+    config: &config::Config,
+    encrypted_file: &str,
+    decrypted_file: &str,
+) -> aes_rust::Result<()> {
+    match config.decrypt_range {
+        Some((start, len)) => cipher.decipher_file_range(encrypted_file, decrypted_file, start, len),
+        None => cipher.decipher_file(encrypted_file, decrypted_file),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Config;
+    use std::path::{Path, PathBuf};
+
+    /// Configuration of `command` with `args`, on a small input file in `dir`, which publishes
+    /// the metrics to the returned Prometheus file there
+    fn prometheus_config(command: &str, dir: &Path, args: &[&str]) -> (Cli, Config, PathBuf) {
+        let input_file = dir.join("plain");
+        let prometheus_file = dir.join("metrics.prom");
+        std::fs::write(&input_file, vec![7; 4096]).unwrap();
+
+        let mut config = Config::default();
+        let common_args = [
+            command,
+            "-i",
+            input_file.to_str().unwrap(),
+            "--key",
+            "000102030405060708090a0b0c0d0e0f",
+            "--metrics",
+            "prometheus",
+            "--prometheus-file",
+            prometheus_file.to_str().unwrap(),
+        ];
+        let args = common_args.iter().chain(args).map(|arg| arg.to_string());
+        let cli = Cli::parse(args, &mut config).unwrap();
+        (cli, config, prometheus_file)
+    }
+
+    #[test]
+    fn test_bench_tags_the_settings_of_the_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let args = ["--task-blocks", "4", "--buffer-blocks", "32"];
+        let (_cli, config, prometheus_file) = prometheus_config("bench", dir.path(), &args);
+        let mut cipher = create_cipher(&config).unwrap();
+        bench(&mut cipher, &config).unwrap();
+
+        let exposition = std::fs::read_to_string(&prometheus_file).unwrap();
+        let implementation = cipher.config().implementation.name();
+        let completion_time = exposition
+            .lines()
+            .find(|line| line.starts_with("aes_cipher_completion_time{"))
+            .unwrap();
+        for label in [
+            format!("implementation=\"{}\"", implementation),
+            "task_blocks=\"4\"".to_string(),
+            "buffer_blocks=\"32\"".to_string(),
+        ] {
+            assert!(completion_time.contains(&label), "{}", completion_time);
+        }
+        // The settings are not encoded in the metric names
+        assert_eq!(exposition.matches("completion_time{").count(), 1);
+    }
+
+    #[test]
+    fn test_sweep_keeps_the_prometheus_series_of_each_thread_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_cli, mut config, prometheus_file) =
+            prometheus_config("sweep", dir.path(), &["--sweep", "1,2"]);
+        sweep(&mut config).unwrap();
+
+        let exposition = std::fs::read_to_string(&prometheus_file).unwrap();
+        for n_threads in [1, 2] {
+            let threads = format!("threads=\"{}\"", n_threads);
+            assert!(
+                exposition
+                    .lines()
+                    .any(|line| line.starts_with("aes_cipher_sweep_speedup{")
+                        && line.contains(&threads)),
+                "no series with {} in:\n{}",
+                threads,
+                exposition
+            );
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use super::MetricsLogger;
+use std::time::Duration;
+
+/// Sends every metric to each of several loggers, in order
+pub struct FanOutMetricsLogger {
+    loggers: Vec<Box<dyn MetricsLogger>>,
+}
+
+impl FanOutMetricsLogger {
+    pub fn new(loggers: Vec<Box<dyn MetricsLogger>>) -> Self {
+        FanOutMetricsLogger { loggers }
+    }
+}
+
+impl MetricsLogger for FanOutMetricsLogger {
+    fn increment(&self, metric: &str) {
+        self.loggers
+            .iter()
+            .for_each(|logger| logger.increment(metric));
+    }
+
+    fn decrement(&self, metric: &str) {
+        self.loggers
+            .iter()
+            .for_each(|logger| logger.decrement(metric));
+    }
+
+    fn gauge(&self, metric: &str, value: f64) {
+        self.loggers
+            .iter()
+            .for_each(|logger| logger.gauge(metric, value));
+    }
+
+    fn timer(&self, metric: &str, duration: Duration) {
+        self.loggers
+            .iter()
+            .for_each(|logger| logger.timer(metric, duration));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Logger that keeps a description of each metric it is sent, shared with the test
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl MetricsLogger for Recorder {
+        fn increment(&self, metric: &str) {
+            self.0.borrow_mut().push(format!("+{}", metric));
+        }
+
+        fn decrement(&self, metric: &str) {
+            self.0.borrow_mut().push(format!("-{}", metric));
+        }
+
+        fn gauge(&self, metric: &str, value: f64) {
+            self.0.borrow_mut().push(format!("{}={}", metric, value));
+        }
+
+        fn timer(&self, metric: &str, duration: Duration) {
+            let millis = duration.as_millis();
+            self.0.borrow_mut().push(format!("{}={}ms", metric, millis));
+        }
+    }
+
+    #[test]
+    fn test_every_logger_receives_the_metrics() {
+        let records: Vec<_> = (0..2).map(|_| Rc::new(RefCell::new(Vec::new()))).collect();
+        let logger = FanOutMetricsLogger::new(
+            records
+                .iter()
+                .map(|record| Box::new(Recorder(record.clone())) as Box<dyn MetricsLogger>)
+                .collect(),
+        );
+
+        logger.increment("runs");
+        logger.decrement("pending");
+        logger.gauge("n_threads", 4.0);
+        logger.run_and_measure("noop", || ());
+
+        for record in records {
+            assert_eq!(
+                *record.borrow(),
+                ["+runs", "-pending", "n_threads=4", "noop=0ms"]
+            );
+        }
+    }
+}
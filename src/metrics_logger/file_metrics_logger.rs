@@ -0,0 +1,218 @@
+use super::{unix_time_millis, MetricType, MetricsLogger, Tags};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Format of the records written by a [`FileMetricsLogger`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    /// One JSON object per line, such as
+    /// `{"timestamp":1700000000000,"type":"gauge","metric":"aes_cipher.n_threads","value":4,"tags":{"mode":"ctr"}}`
+    JsonLines,
+    /// A `timestamp,type,metric,value,tags` header followed by one row per metric, with the tags
+    /// joined as `name=value;...`
+    Csv,
+}
+
+const CSV_HEADER: &str = "timestamp,type,metric,value,tags";
+
+/// Appends every metric to a file, to analyse runs offline. Timers are written in seconds.
+pub struct FileMetricsLogger {
+    writer: Mutex<BufWriter<File>>,
+    format: FileFormat,
+    prefix: String,
+    tags: Tags,
+}
+
+impl FileMetricsLogger {
+    /// Open the file in append mode, so that the records of successive runs add up. A CSV
+    /// header is written when the file is empty.
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        format: FileFormat,
+        prefix: &str,
+        tags: Tags,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_empty = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+        if format == FileFormat::Csv && is_empty {
+            writeln!(writer, "{}", CSV_HEADER)?;
+            writer.flush()?;
+        }
+
+        Ok(FileMetricsLogger {
+            writer: Mutex::new(writer),
+            format,
+            prefix: prefix.to_string(),
+            tags,
+        })
+    }
+
+    fn record(&self, metric_type: MetricType, metric: &str, value: f64) -> String {
+        let timestamp = unix_time_millis();
+        let metric = format!("{}.{}", self.prefix, metric);
+        match self.format {
+            FileFormat::JsonLines => {
+                let tags: Vec<String> = self
+                    .tags
+                    .iter()
+                    .map(|(name, value)| format!("{}:{}", json_string(name), json_string(value)))
+                    .collect();
+                // JSON has no representation for NaN or infinities
+                let value = if value.is_finite() {
+                    value.to_string()
+                } else {
+                    "null".to_string()
+                };
+                format!(
+                    "{{\"timestamp\":{},\"type\":\"{}\",\"metric\":{},\"value\":{},\"tags\":{{{}}}}}",
+                    timestamp,
+                    metric_type.name(),
+                    json_string(&metric),
+                    value,
+                    tags.join(",")
+                )
+            }
+            FileFormat::Csv => {
+                let tags: Vec<String> = self
+                    .tags
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect();
+                format!(
+                    "{},{},{},{},{}",
+                    timestamp,
+                    metric_type.name(),
+                    csv_field(&metric),
+                    value,
+                    csv_field(&tags.join(";"))
+                )
+            }
+        }
+    }
+
+    /// Metrics are written on a best-effort basis, like the other loggers, so a full disk does
+    /// not fail the run. Each record is flushed, so that an interrupted run keeps its metrics.
+    fn write(&self, metric_type: MetricType, metric: &str, value: f64) {
+        let record = self.record(metric_type, metric, value);
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(writer, "{}", record)
+            .and_then(|_| writer.flush())
+            .ok();
+    }
+}
+
+impl MetricsLogger for FileMetricsLogger {
+    fn increment(&self, metric: &str) {
+        self.write(MetricType::Counter, metric, 1.0);
+    }
+
+    fn decrement(&self, metric: &str) {
+        self.write(MetricType::Counter, metric, -1.0);
+    }
+
+    fn gauge(&self, metric: &str, value: f64) {
+        self.write(MetricType::Gauge, metric, value);
+    }
+
+    fn timer(&self, metric: &str, duration: Duration) {
+        self.write(MetricType::Timer, metric, duration.as_secs_f64());
+    }
+}
+
+/// Quote and escape a string as a JSON string
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Quote a CSV field when it contains a separator, a quote or a line break
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Drop the timestamp that starts each record, which changes from run to run
+    fn without_timestamps(content: &str, separator: &str) -> Vec<String> {
+        content
+            .lines()
+            .map(|line| match line.split_once(separator) {
+                Some((_, rest)) => rest.to_string(),
+                None => line.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics.jsonl");
+        let tags = Tags::default().with("mode", "ctr").with("run_id", "a\"b");
+        let logger = FileMetricsLogger::new(&path, FileFormat::JsonLines, "aes", tags).unwrap();
+        logger.gauge("n_threads", 4.0);
+        logger.timer("read_time", Duration::from_millis(1500));
+        logger.gauge("throughput", f64::NAN);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            without_timestamps(&content, ","),
+            [
+                r#""type":"gauge","metric":"aes.n_threads","value":4,"tags":{"mode":"ctr","run_id":"a\"b"}}"#,
+                r#""type":"timer","metric":"aes.read_time","value":1.5,"tags":{"mode":"ctr","run_id":"a\"b"}}"#,
+                r#""type":"gauge","metric":"aes.throughput","value":null,"tags":{"mode":"ctr","run_id":"a\"b"}}"#,
+            ]
+        );
+        assert!(content.starts_with("{\"timestamp\":"));
+    }
+
+    #[test]
+    fn test_csv_appends_after_a_single_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics.csv");
+        for run_id in ["1", "2"] {
+            let tags = Tags::default().with("mode", "ctr").with("run_id", run_id);
+            let logger = FileMetricsLogger::new(&path, FileFormat::Csv, "aes", tags).unwrap();
+            logger.increment("runs");
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            without_timestamps(&content, ","),
+            [
+                "type,metric,value,tags",
+                "counter,aes.runs,1,mode=ctr;run_id=1",
+                "counter,aes.runs,1,mode=ctr;run_id=2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(json_string("a\\b\n\u{1}"), r#""a\\b\n\u0001""#);
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,\"b\""), r#""a,""b""""#);
+    }
+}
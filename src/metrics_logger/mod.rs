@@ -1,5 +1,24 @@
+/*
+Destinations of the metrics of a run. StatsD feeds the Graphite and Grafana containers, and the
+other loggers cover local and CI runs: stdout to read them, JSON lines or CSV files to analyse
+them offline, and a Prometheus text file for the node exporter. A fan-out logger sends the
+metrics to several of them at once.
+ */
+pub mod fan_out_metrics_logger;
+pub mod file_metrics_logger;
+pub mod noop_metrics_logger;
+pub mod prometheus_metrics_logger;
 pub mod statsd_metrics_logger;
+pub mod stdout_metrics_logger;
+
+pub use fan_out_metrics_logger::FanOutMetricsLogger;
+pub use file_metrics_logger::{FileFormat, FileMetricsLogger};
+pub use noop_metrics_logger::NoopMetricsLogger;
+pub use prometheus_metrics_logger::PrometheusMetricsLogger;
 pub use statsd_metrics_logger::StatsDMetricsLogger;
+pub use stdout_metrics_logger::StdoutMetricsLogger;
+
+use std::time::{Duration, Instant};
 
 pub trait MetricsLogger {
     fn increment(&self, metric: &str);
@@ -8,7 +27,188 @@ pub trait MetricsLogger {
 
     fn gauge(&self, metric: &str, value: f64);
 
+    /// Report a duration that was measured elsewhere. `run_and_measure` can only time a closure
+    /// as a whole, while the time of each phase is added up over the threads and iterations of
+    /// a run inside the cipher, so it is reported with this method instead. Loggers that have
+    /// no timer type can keep the default, which reports the duration as a gauge in seconds.
+    fn timer(&self, metric: &str, duration: Duration) {
+        self.gauge(metric, duration.as_secs_f64());
+    }
+
     fn run_and_measure<F, T>(&self, metric: &str, f: F) -> T
     where
-        F: Fn() -> T;
+        F: Fn() -> T,
+        Self: Sized,
+    {
+        let start_time = Instant::now();
+        let result = f();
+        self.timer(metric, start_time.elapsed());
+        result
+    }
+}
+
+/// Type of a metric, as the loggers that keep it name it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+    Timer,
+}
+
+impl MetricType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+            MetricType::Timer => "timer",
+        }
+    }
+}
+
+/// Tags attached to every metric of a logger, such as the settings of the run, so that runs
+/// with different configurations can be told apart
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tags(Vec<(String, String)>);
+
+impl Tags {
+    pub fn with<V: std::fmt::Display>(mut self, name: &str, value: V) -> Self {
+        self.0.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Destination of the metrics that can be selected in the configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricsBackend {
+    StatsD,
+    Stdout,
+    JsonLines,
+    Csv,
+    Prometheus,
+    Noop,
+}
+
+impl MetricsBackend {
+    /// Parse a comma-separated list of backends, such as `statsd,stdout`
+    pub fn parse_list(s: &str) -> Result<Vec<Self>, String> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+}
+
+impl std::str::FromStr for MetricsBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "statsd" => Ok(MetricsBackend::StatsD),
+            "stdout" => Ok(MetricsBackend::Stdout),
+            "jsonl" | "json" => Ok(MetricsBackend::JsonLines),
+            "csv" => Ok(MetricsBackend::Csv),
+            "prometheus" => Ok(MetricsBackend::Prometheus),
+            "noop" | "none" => Ok(MetricsBackend::Noop),
+            _ => Err(format!(
+                "Unknown metrics backend: {} (expected statsd, stdout, jsonl, csv, prometheus or \
+                 noop)",
+                s
+            )),
+        }
+    }
+}
+
+/// Milliseconds since the Unix epoch, to timestamp the metrics written to files
+fn unix_time_millis() -> u128 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Logger that keeps the timers it is sent
+    #[derive(Default)]
+    struct TimerRecorder(RefCell<Vec<(String, Duration)>>);
+
+    impl MetricsLogger for TimerRecorder {
+        fn increment(&self, _metric: &str) {}
+
+        fn decrement(&self, _metric: &str) {}
+
+        fn gauge(&self, _metric: &str, _value: f64) {}
+
+        fn timer(&self, metric: &str, duration: Duration) {
+            self.0.borrow_mut().push((metric.to_string(), duration));
+        }
+    }
+
+    #[test]
+    fn test_run_and_measure() {
+        let logger = TimerRecorder::default();
+        let result = logger.run_and_measure("sleep", || {
+            std::thread::sleep(Duration::from_millis(2));
+            42
+        });
+
+        assert_eq!(result, 42);
+        let timers = logger.0.into_inner();
+        assert_eq!(timers.len(), 1);
+        assert_eq!(timers[0].0, "sleep");
+        assert!(timers[0].1 >= Duration::from_millis(2));
+    }
+
+    /// Logger that keeps the gauges it is sent, and reports timers as gauges by default
+    #[derive(Default)]
+    struct GaugeRecorder(RefCell<Vec<(String, f64)>>);
+
+    impl MetricsLogger for GaugeRecorder {
+        fn increment(&self, _metric: &str) {}
+
+        fn decrement(&self, _metric: &str) {}
+
+        fn gauge(&self, metric: &str, value: f64) {
+            self.0.borrow_mut().push((metric.to_string(), value));
+        }
+    }
+
+    #[test]
+    fn test_timer_defaults_to_a_gauge_in_seconds() {
+        let logger = GaugeRecorder::default();
+        logger.timer("read_time", Duration::from_millis(1500));
+        assert_eq!(logger.0.into_inner(), [("read_time".to_string(), 1.5)]);
+    }
+
+    #[test]
+    fn test_parse_backends() {
+        assert_eq!(
+            MetricsBackend::parse_list("statsd, stdout,jsonl,CSV,prometheus,none"),
+            Ok(vec![
+                MetricsBackend::StatsD,
+                MetricsBackend::Stdout,
+                MetricsBackend::JsonLines,
+                MetricsBackend::Csv,
+                MetricsBackend::Prometheus,
+                MetricsBackend::Noop,
+            ])
+        );
+        assert_eq!(MetricsBackend::parse_list(""), Ok(vec![]));
+        assert!(MetricsBackend::parse_list("statsd,graphite")
+            .unwrap_err()
+            .contains("graphite"));
+    }
 }
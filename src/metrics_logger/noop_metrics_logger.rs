@@ -0,0 +1,12 @@
+use super::MetricsLogger;
+
+/// Discards every metric, for runs that do not publish them
+pub struct NoopMetricsLogger;
+
+impl MetricsLogger for NoopMetricsLogger {
+    fn increment(&self, _metric: &str) {}
+
+    fn decrement(&self, _metric: &str) {}
+
+    fn gauge(&self, _metric: &str, _value: f64) {}
+}
@@ -0,0 +1,230 @@
+use super::{MetricsLogger, Tags};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Keeps the latest value of every metric in a file in the Prometheus text exposition format,
+/// for the textfile collector of the node exporter to scrape. The file is rewritten on each
+/// update, through a temporary file renamed over it so that a scrape never reads half of it.
+///
+/// Names are sanitized to the characters Prometheus accepts, and the tags become labels.
+/// Counters accumulate, gauges keep their last value, and timers become `<name>_seconds`
+/// gauges. Loggers created with `with_tags` write their series to the same file.
+pub struct PrometheusMetricsLogger {
+    path: Arc<PathBuf>,
+    prefix: String,
+    labels: String,
+    /// Samples of every series in the file, by name and labels, shared with the loggers created
+    /// with `with_tags`
+    samples: Arc<Mutex<BTreeMap<(String, String), Sample>>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    kind: &'static str,
+    value: f64,
+}
+
+impl PrometheusMetricsLogger {
+    /// Check that the file can be written, so that a wrong path fails at start-up rather than
+    /// losing the metrics silently
+    pub fn new<P: AsRef<Path>>(path: P, prefix: &str, tags: Tags) -> io::Result<Self> {
+        let logger = PrometheusMetricsLogger {
+            path: Arc::new(path.as_ref().to_path_buf()),
+            prefix: sanitize(prefix),
+            labels: labels(&tags),
+            samples: Arc::new(Mutex::new(BTreeMap::new())),
+        };
+        logger.write(&logger.samples.lock().unwrap())?;
+        Ok(logger)
+    }
+
+    /// Logger of the same file and prefix with other tags, such as one for each thread count of
+    /// a sweep, whose series are kept next to the ones of this logger instead of replacing them
+    pub fn with_tags(&self, tags: Tags) -> Self {
+        PrometheusMetricsLogger {
+            path: self.path.clone(),
+            prefix: self.prefix.clone(),
+            labels: labels(&tags),
+            samples: self.samples.clone(),
+        }
+    }
+
+    fn name(&self, metric: &str) -> String {
+        format!("{}_{}", self.prefix, sanitize(metric))
+    }
+
+    /// The series of each metric follow its `# TYPE` line, which may only appear once
+    fn exposition(samples: &BTreeMap<(String, String), Sample>) -> String {
+        let mut exposition = String::new();
+        let mut previous_name = None;
+        for ((name, labels), sample) in samples {
+            if previous_name != Some(name) {
+                exposition.push_str(&format!("# TYPE {} {}\n", name, sample.kind));
+                previous_name = Some(name);
+            }
+            exposition.push_str(&format!(
+                "{}{} {}\n",
+                name,
+                labels,
+                format_value(sample.value)
+            ));
+        }
+        exposition
+    }
+
+    fn write(&self, samples: &BTreeMap<(String, String), Sample>) -> io::Result<()> {
+        let mut temp_path = self.path.as_os_str().to_os_string();
+        temp_path.push(".tmp");
+        std::fs::write(&temp_path, Self::exposition(samples))?;
+        std::fs::rename(&temp_path, self.path.as_ref())
+    }
+
+    /// Metrics are written on a best-effort basis, like the other loggers
+    fn update(&self, name: String, kind: &'static str, f: impl FnOnce(&mut f64)) {
+        let mut samples = self.samples.lock().unwrap();
+        let sample = samples
+            .entry((name, self.labels.clone()))
+            .or_insert(Sample { kind, value: 0.0 });
+        sample.kind = kind;
+        f(&mut sample.value);
+        self.write(&samples).ok();
+    }
+}
+
+impl MetricsLogger for PrometheusMetricsLogger {
+    fn increment(&self, metric: &str) {
+        self.update(self.name(metric), "counter", |value| *value += 1.0);
+    }
+
+    /// Prometheus counters only go up, so a decremented metric is exposed as a gauge
+    fn decrement(&self, metric: &str) {
+        self.update(self.name(metric), "gauge", |value| *value -= 1.0);
+    }
+
+    fn gauge(&self, metric: &str, value: f64) {
+        self.update(self.name(metric), "gauge", |sample| *sample = value);
+    }
+
+    fn timer(&self, metric: &str, duration: Duration) {
+        let name = format!("{}_seconds", self.name(metric));
+        self.update(name, "gauge", |sample| *sample = duration.as_secs_f64());
+    }
+}
+
+/// Labels of the tags, in the `{name="value",...}` format, or nothing without tags
+fn labels(tags: &Tags) -> String {
+    let labels: Vec<String> = tags
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", sanitize(name), escape_label(value)))
+        .collect();
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+/// Replace the characters that are not allowed in metric and label names with underscores
+fn sanitize(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exposition_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics.prom");
+        let tags = Tags::default().with("mode", "ctr").with("run id", "a\"b");
+        let logger = PrometheusMetricsLogger::new(&path, "aes", tags).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+
+        logger.increment("runs");
+        logger.increment("runs");
+        logger.gauge("n_threads", 4.0);
+        logger.gauge("iteration_time.p95", 0.25);
+        logger.timer("read_time", Duration::from_millis(1500));
+        logger.gauge("throughput", f64::INFINITY);
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "# TYPE aes_iteration_time_p95 gauge\n\
+             aes_iteration_time_p95{mode=\"ctr\",run_id=\"a\\\"b\"} 0.25\n\
+             # TYPE aes_n_threads gauge\n\
+             aes_n_threads{mode=\"ctr\",run_id=\"a\\\"b\"} 4\n\
+             # TYPE aes_read_time_seconds gauge\n\
+             aes_read_time_seconds{mode=\"ctr\",run_id=\"a\\\"b\"} 1.5\n\
+             # TYPE aes_runs counter\n\
+             aes_runs{mode=\"ctr\",run_id=\"a\\\"b\"} 2\n\
+             # TYPE aes_throughput gauge\n\
+             aes_throughput{mode=\"ctr\",run_id=\"a\\\"b\"} +Inf\n"
+        );
+    }
+
+    #[test]
+    fn test_loggers_with_other_tags_share_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics.prom");
+        let logger = PrometheusMetricsLogger::new(&path, "aes", Tags::default()).unwrap();
+        logger.gauge("n_threads", 1.0);
+        for n_threads in [1, 2] {
+            let tags = Tags::default().with("threads", n_threads);
+            logger
+                .with_tags(tags)
+                .gauge("speedup", n_threads as f64 * 0.75);
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "# TYPE aes_n_threads gauge\n\
+             aes_n_threads 1\n\
+             # TYPE aes_speedup gauge\n\
+             aes_speedup{threads=\"1\"} 0.75\n\
+             aes_speedup{threads=\"2\"} 1.5\n"
+        );
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(
+            sanitize("aes_cipher.read-time:ms"),
+            "aes_cipher_read_time:ms"
+        );
+        assert_eq!(sanitize("95th"), "_95th");
+    }
+}
@@ -1,46 +1,115 @@
-extern crate statsd;
-
-use super::MetricsLogger;
-use std::net::ToSocketAddrs;
+use super::{MetricsLogger, Tags};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
 
+/// Sends the metrics over UDP to a StatsD server, such as the one in the graphite container.
+/// Tags are appended in the DogStatsD format, `<metric>:<value>|<type>|#<name>:<value>,...`,
+/// which StatsD forwards to Graphite as tagged series.
 pub struct StatsDMetricsLogger {
-    statsd_client: statsd::Client,
+    socket: UdpSocket,
+    server: SocketAddr,
+    prefix: String,
+    /// Tags of every metric, already formatted as the `|#...` suffix, or empty without tags
+    tags: String,
 }
 
 impl StatsDMetricsLogger {
-    pub fn new<T: ToSocketAddrs>(host: T, prefix: &str) -> Self {
-        let statsd_client =
-            statsd::Client::new(host, prefix).expect("Failed to create statsd client");
+    /// Resolve the host of the server and bind the socket the metrics are sent from. Fails if
+    /// the host cannot be resolved, such as the graphite container outside of its network.
+    pub fn new<T: ToSocketAddrs>(host: T, prefix: &str) -> io::Result<Self> {
+        let server = host.to_socket_addrs()?.next().ok_or(io::Error::new(
+            io::ErrorKind::NotFound,
+            "the statsd host has no address",
+        ))?;
+        let local_addr = match server {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        };
+        let socket = UdpSocket::bind(local_addr)?;
 
-        StatsDMetricsLogger { statsd_client }
+        Ok(StatsDMetricsLogger {
+            socket,
+            server,
+            prefix: prefix.to_string(),
+            tags: String::new(),
+        })
+    }
+
+    /// Add a tag to every metric sent by the logger. The characters that separate the tags in
+    /// the DogStatsD format are replaced with underscores.
+    pub fn with_tag<V: std::fmt::Display>(mut self, name: &str, value: V) -> Self {
+        let sanitize = |s: String| s.replace([',', '|', '#', ':', '\n'], "_");
+        self.tags
+            .push_str(if self.tags.is_empty() { "|#" } else { "," });
+        self.tags.push_str(&sanitize(name.to_string()));
+        self.tags.push(':');
+        self.tags.push_str(&sanitize(value.to_string()));
+        self
     }
-}
 
-impl Default for StatsDMetricsLogger {
-    fn default() -> Self {
-        let host = "graphite:8125";
-        let prefix = "aes_cipher";
-        Self::new(host, prefix)
+    /// Add each of the tags to every metric sent by the logger
+    pub fn with_tags(self, tags: &Tags) -> Self {
+        tags.iter()
+            .fold(self, |logger, (name, value)| logger.with_tag(name, value))
+    }
+
+    /// Metrics are sent on a best-effort basis, as usual with StatsD, so a missing server does
+    /// not fail the run
+    fn send(&self, metric: &str, value: f64, kind: &str) {
+        let data = format!("{}.{}:{}|{}{}", self.prefix, metric, value, kind, self.tags);
+        self.socket.send_to(data.as_bytes(), self.server).ok();
     }
 }
 
 impl MetricsLogger for StatsDMetricsLogger {
     fn increment(&self, metric: &str) {
-        self.statsd_client.incr(metric);
+        self.send(metric, 1.0, "c");
     }
 
     fn decrement(&self, metric: &str) {
-        self.statsd_client.decr(metric);
+        self.send(metric, -1.0, "c");
     }
 
     fn gauge(&self, metric: &str, value: f64) {
-        self.statsd_client.gauge(metric, value);
+        self.send(metric, value, "g");
+    }
+
+    fn timer(&self, metric: &str, duration: Duration) {
+        self.send(metric, duration.as_secs_f64() * 1000.0, "ms");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_are_sent_with_tags() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let receive = || {
+            let mut buffer = [0; 512];
+            let len = server.recv(&mut buffer).unwrap();
+            String::from_utf8(buffer[..len].to_vec()).unwrap()
+        };
+
+        let logger = StatsDMetricsLogger::new(server.local_addr().unwrap(), "aes").unwrap();
+        logger.gauge("n_threads", 4.0);
+        assert_eq!(receive(), "aes.n_threads:4|g");
+
+        let tags = Tags::default().with("mode", "ctr");
+        let logger = logger.with_tags(&tags).with_tag("run_id", "a,b|c");
+        logger.increment("runs");
+        assert_eq!(receive(), "aes.runs:1|c|#mode:ctr,run_id:a_b_c");
+        logger.timer("read_time", Duration::from_micros(1500));
+        assert_eq!(receive(), "aes.read_time:1.5|ms|#mode:ctr,run_id:a_b_c");
     }
 
-    fn run_and_measure<F, T>(&self, metric: &str, f: F) -> T
-    where
-        F: Fn() -> T,
-    {
-        self.statsd_client.time(metric, f)
+    #[test]
+    fn test_unresolvable_host_is_an_error() {
+        assert!(StatsDMetricsLogger::new("statsd.invalid:8125", "aes").is_err());
     }
 }
@@ -0,0 +1,80 @@
+use super::{MetricType, MetricsLogger, Tags};
+use std::time::Duration;
+
+/// Prints every metric on its own line, in a human-readable format such as
+/// `[metrics] gauge aes_cipher.n_threads = 4 (mode=ctr, run_id=1a2b3c4d)`
+pub struct StdoutMetricsLogger {
+    prefix: String,
+    tags: Tags,
+}
+
+impl StdoutMetricsLogger {
+    pub fn new(prefix: &str, tags: Tags) -> Self {
+        StdoutMetricsLogger {
+            prefix: prefix.to_string(),
+            tags,
+        }
+    }
+
+    fn format(&self, metric_type: MetricType, metric: &str, value: &str) -> String {
+        let mut line = format!(
+            "[metrics] {} {}.{} = {}",
+            metric_type.name(),
+            self.prefix,
+            metric,
+            value
+        );
+        if !self.tags.is_empty() {
+            let tags: Vec<String> = self
+                .tags
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            line.push_str(&format!(" ({})", tags.join(", ")));
+        }
+        line
+    }
+}
+
+impl MetricsLogger for StdoutMetricsLogger {
+    fn increment(&self, metric: &str) {
+        println!("{}", self.format(MetricType::Counter, metric, "+1"));
+    }
+
+    fn decrement(&self, metric: &str) {
+        println!("{}", self.format(MetricType::Counter, metric, "-1"));
+    }
+
+    fn gauge(&self, metric: &str, value: f64) {
+        println!(
+            "{}",
+            self.format(MetricType::Gauge, metric, &value.to_string())
+        );
+    }
+
+    fn timer(&self, metric: &str, duration: Duration) {
+        let value = format!("{}s", duration.as_secs_f64());
+        println!("{}", self.format(MetricType::Timer, metric, &value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let logger = StdoutMetricsLogger::new("aes", Tags::default());
+        assert_eq!(
+            logger.format(MetricType::Gauge, "n_threads", "4"),
+            "[metrics] gauge aes.n_threads = 4"
+        );
+
+        let tags = Tags::default().with("mode", "ctr").with("threads", 2);
+        let logger = StdoutMetricsLogger::new("aes", tags);
+        assert_eq!(
+            logger.format(MetricType::Timer, "read_time", "1.5s"),
+            "[metrics] timer aes.read_time = 1.5s (mode=ctr, threads=2)"
+        );
+    }
+}
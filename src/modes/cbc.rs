@@ -0,0 +1,178 @@
+/*
+Cipher Block Chaining mode, as defined in NIST SP 800-38A section 6.2
+ */
+use super::xor_block;
+use crate::aes_block_cipher::{BlockCipher, N_B};
+use crate::concurrency::Executor;
+
+/// Cipher the blocks in place, chaining each of them with the previous ciphertext block.
+/// `iv` is updated to the last ciphertext block, so consecutive calls continue the chain.
+pub fn cipher_blocks(
+    block_cipher: &dyn BlockCipher,
+    iv: &mut [u8; 4 * N_B],
+    blocks: &mut [[u8; 4 * N_B]],
+) {
+    for block in blocks.iter_mut() {
+        xor_block(block, iv);
+        block_cipher.cipher_block_in_place(block);
+        *iv = *block;
+    }
+}
+
+/// Decipher the blocks in place using the executor. Unlike encryption, each plaintext block
+/// only depends on two ciphertext blocks, so all of them can be computed in parallel. Since the
+/// blocks are overwritten, the ciphertext is first copied to `ciphertext`, which the caller keeps
+/// so that its allocation is reused for every batch.
+/// `iv` is updated to the last ciphertext block, so consecutive calls continue the chain.
+pub fn decipher_blocks(
+    block_cipher: &dyn BlockCipher,
+    executor: &dyn Executor,
+    iv: &mut [u8; 4 * N_B],
+    blocks: &mut [[u8; 4 * N_B]],
+    ciphertext: &mut Vec<[u8; 4 * N_B]>,
+) {
+    ciphertext.clear();
+    ciphertext.extend_from_slice(blocks);
+    decipher_copied_blocks(block_cipher, executor, iv, ciphertext, blocks);
+}
+
+/// Decipher the blocks in place like `decipher_blocks`, when their ciphertext is already
+/// available elsewhere, such as in the memory map of the input file, so it is not copied.
+pub fn decipher_copied_blocks(
+    block_cipher: &dyn BlockCipher,
+    executor: &dyn Executor,
+    iv: &mut [u8; 4 * N_B],
+    ciphertext: &[[u8; 4 * N_B]],
+    blocks: &mut [[u8; 4 * N_B]],
+) {
+    let first_prev_block = *iv;
+    let chunk_blocks = block_cipher.parallel_blocks();
+    executor.for_each_block_chunk(blocks, chunk_blocks, |first_index, chunk| {
+        block_cipher.inv_cipher_blocks(chunk);
+        for (i, block) in (first_index..).zip(chunk.iter_mut()) {
+            let prev_block = if i == 0 {
+                &first_prev_block
+            } else {
+                &ciphertext[i - 1]
+            };
+            xor_block(block, prev_block);
+        }
+    });
+
+    if let Some(last_block) = ciphertext.last() {
+        *iv = *last_block;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes_block_cipher::AESBlockCipher;
+    use crate::modes::test_vectors::{executor, KEY_AES_128, KEY_AES_256, PLAIN_BLOCKS};
+
+    // NIST SP 800-38A, appendix F.2
+    const IV: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+
+    const CIPHER_BLOCKS_AES_128: [[u8; 16]; 4] = [
+        [
+            0x76, 0x49, 0xab, 0xac, 0x81, 0x19, 0xb2, 0x46, 0xce, 0xe9, 0x8e, 0x9b, 0x12, 0xe9,
+            0x19, 0x7d,
+        ],
+        [
+            0x50, 0x86, 0xcb, 0x9b, 0x50, 0x72, 0x19, 0xee, 0x95, 0xdb, 0x11, 0x3a, 0x91, 0x76,
+            0x78, 0xb2,
+        ],
+        [
+            0x73, 0xbe, 0xd6, 0xb8, 0xe3, 0xc1, 0x74, 0x3b, 0x71, 0x16, 0xe6, 0x9e, 0x22, 0x22,
+            0x95, 0x16,
+        ],
+        [
+            0x3f, 0xf1, 0xca, 0xa1, 0x68, 0x1f, 0xac, 0x09, 0x12, 0x0e, 0xca, 0x30, 0x75, 0x86,
+            0xe1, 0xa7,
+        ],
+    ];
+
+    const CIPHER_BLOCKS_AES_256: [[u8; 16]; 4] = [
+        [
+            0xf5, 0x8c, 0x4c, 0x04, 0xd6, 0xe5, 0xf1, 0xba, 0x77, 0x9e, 0xab, 0xfb, 0x5f, 0x7b,
+            0xfb, 0xd6,
+        ],
+        [
+            0x9c, 0xfc, 0x4e, 0x96, 0x7e, 0xdb, 0x80, 0x8d, 0x67, 0x9f, 0x77, 0x7b, 0xc6, 0x70,
+            0x2c, 0x7d,
+        ],
+        [
+            0x39, 0xf2, 0x33, 0x69, 0xa9, 0xd9, 0xba, 0xcf, 0xa5, 0x30, 0xe2, 0x63, 0x04, 0x23,
+            0x14, 0x61,
+        ],
+        [
+            0xb2, 0xeb, 0x05, 0xe2, 0xc3, 0x9b, 0xe9, 0xfc, 0xda, 0x6c, 0x19, 0x07, 0x8c, 0x6a,
+            0x9d, 0x1b,
+        ],
+    ];
+
+    #[test]
+    fn test_cbc_aes_128_encrypt() {
+        let block_cipher = AESBlockCipher::new(&KEY_AES_128).unwrap();
+        let mut iv = IV;
+        let mut blocks = PLAIN_BLOCKS;
+
+        cipher_blocks(&block_cipher, &mut iv, &mut blocks);
+
+        assert_eq!(blocks, CIPHER_BLOCKS_AES_128);
+        assert_eq!(iv, CIPHER_BLOCKS_AES_128[3]);
+    }
+
+    #[test]
+    fn test_cbc_aes_128_decrypt() {
+        let block_cipher = AESBlockCipher::new(&KEY_AES_128).unwrap();
+        let mut iv = IV;
+        let mut blocks = CIPHER_BLOCKS_AES_128;
+
+        decipher_blocks(
+            &block_cipher,
+            &executor(),
+            &mut iv,
+            &mut blocks,
+            &mut Vec::new(),
+        );
+
+        assert_eq!(blocks, PLAIN_BLOCKS);
+        assert_eq!(iv, CIPHER_BLOCKS_AES_128[3]);
+    }
+
+    #[test]
+    fn test_cbc_aes_256_encrypt_in_several_calls() {
+        let block_cipher = AESBlockCipher::new(&KEY_AES_256).unwrap();
+        let mut iv = IV;
+        let mut blocks = PLAIN_BLOCKS;
+
+        let (first_half, second_half) = blocks.split_at_mut(1);
+        cipher_blocks(&block_cipher, &mut iv, first_half);
+        cipher_blocks(&block_cipher, &mut iv, second_half);
+        assert_eq!(blocks, CIPHER_BLOCKS_AES_256);
+
+        let mut iv = IV;
+        let executor = executor();
+        let mut ciphertext = Vec::new();
+        let (first_blocks, last_block) = blocks.split_at_mut(3);
+        decipher_blocks(
+            &block_cipher,
+            &executor,
+            &mut iv,
+            first_blocks,
+            &mut ciphertext,
+        );
+        decipher_blocks(
+            &block_cipher,
+            &executor,
+            &mut iv,
+            last_block,
+            &mut ciphertext,
+        );
+        assert_eq!(blocks, PLAIN_BLOCKS);
+    }
+}
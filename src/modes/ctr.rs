@@ -0,0 +1,336 @@
+/*
+Counter mode, as defined in NIST SP 800-38A section 6.5.
+Every keystream block only depends on its position, so the keystream can be generated in
+parallel and any byte range can be processed without touching the data before it.
+ */
+use crate::aes_block_cipher::{BlockCipher, MAX_PARALLEL_BLOCKS, N_B};
+use crate::concurrency::Executor;
+use crate::{Error, Result};
+
+/// How the counter block is split between a fixed nonce and the incremented counter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterLayout {
+    /// 64-bit nonce followed by a 64-bit big-endian counter, which wraps around without
+    /// modifying the nonce
+    Ctr64,
+    /// The whole block is a 128-bit big-endian counter
+    Ctr128,
+    /// 96-bit nonce followed by a 32-bit big-endian counter, as used by GCM
+    Ctr32,
+}
+
+impl CounterLayout {
+    /// Returns a random initial counter block. Only the nonce is random, and the counter
+    /// starts at zero.
+    pub fn random_initial_counter(&self) -> [u8; 4 * N_B] {
+        let mut initial_counter: [u8; 4 * N_B] = rand::random();
+        match self {
+            CounterLayout::Ctr32 => initial_counter[12..].fill(0),
+            CounterLayout::Ctr64 => initial_counter[8..].fill(0),
+            CounterLayout::Ctr128 => {}
+        }
+        initial_counter
+    }
+
+    /// Checks that the `len` bytes at byte `offset` of a message are within the 2^32 or 2^64
+    /// blocks the counter can take, since past them it would wrap back to the initial counter
+    /// block and reuse the keystream
+    pub fn check_range(&self, offset: u64, len: u64) -> Result<()> {
+        let counter_bits = match self {
+            CounterLayout::Ctr32 => 32,
+            CounterLayout::Ctr64 => 64,
+            CounterLayout::Ctr128 => 128,
+        };
+        // Byte offsets are 64-bit, so only the 32-bit counter can run out of blocks when the
+        // range fits in them
+        let end_block = offset
+            .checked_add(len)
+            .map(|end| end.div_ceil(4 * N_B as u64));
+        match end_block {
+            _ if len == 0 => Ok(()),
+            Some(end_block) if counter_bits > 32 || end_block <= 1 << counter_bits => Ok(()),
+            _ => Err(Error::Unsupported(format!(
+                "the message is longer than the 2^{} blocks of a {}-bit counter",
+                counter_bits, counter_bits
+            ))),
+        }
+    }
+
+    /// Returns the counter block used to generate the keystream block at `block_index`
+    pub fn counter_block(
+        &self,
+        initial_counter: &[u8; 4 * N_B],
+        block_index: u64,
+    ) -> [u8; 4 * N_B] {
+        match self {
+            CounterLayout::Ctr64 => {
+                let mut counter_block = *initial_counter;
+                let counter = u64::from_be_bytes(initial_counter[8..].try_into().unwrap());
+                counter_block[8..]
+                    .copy_from_slice(&counter.wrapping_add(block_index).to_be_bytes());
+                counter_block
+            }
+            CounterLayout::Ctr32 => {
+                let mut counter_block = *initial_counter;
+                let counter = u32::from_be_bytes(initial_counter[12..].try_into().unwrap());
+                counter_block[12..]
+                    .copy_from_slice(&counter.wrapping_add(block_index as u32).to_be_bytes());
+                counter_block
+            }
+            CounterLayout::Ctr128 => u128::from_be_bytes(*initial_counter)
+                .wrapping_add(block_index as u128)
+                .to_be_bytes(),
+        }
+    }
+}
+
+fn keystream_block(
+    block_cipher: &dyn BlockCipher,
+    initial_counter: &[u8; 4 * N_B],
+    layout: CounterLayout,
+    block_index: u64,
+) -> [u8; 4 * N_B] {
+    let mut keystream = layout.counter_block(initial_counter, block_index);
+    block_cipher.cipher_block_in_place(&mut keystream);
+    keystream
+}
+
+/// XOR `data` with the keystream starting at byte `offset` of the message. Since encryption and
+/// decryption are the same operation, this is used for both.
+/// Neither `offset` nor the length of `data` need to be multiples of the block size. Fails
+/// without changing `data` if the counter would wrap around.
+pub fn apply_keystream(
+    block_cipher: &dyn BlockCipher,
+    executor: &dyn Executor,
+    initial_counter: &[u8; 4 * N_B],
+    layout: CounterLayout,
+    offset: u64,
+    data: &mut [u8],
+) -> Result<()> {
+    layout.check_range(offset, data.len() as u64)?;
+
+    let block_size = 4 * N_B as u64;
+    let first_block_index = offset / block_size;
+    let skip = (offset % block_size) as usize;
+
+    // Bytes up to the next block boundary use the tail of a keystream block
+    let head_len = ((4 * N_B - skip) % (4 * N_B)).min(data.len());
+    let (head, rest) = data.split_at_mut(head_len);
+    if !head.is_empty() {
+        let keystream = keystream_block(block_cipher, initial_counter, layout, first_block_index);
+        head.iter_mut()
+            .zip(&keystream[skip..])
+            .for_each(|(byte, key_byte)| *byte ^= key_byte);
+    }
+
+    // The keystream of each chunk is ciphered at once, so it holds as many blocks as the block
+    // cipher processes in parallel
+    let rest_first_block_index = first_block_index + (skip > 0) as u64;
+    let chunk_blocks = block_cipher.parallel_blocks();
+    executor.for_each_chunk(rest, 4 * N_B * chunk_blocks, &|i, chunk| {
+        let first_block_index = rest_first_block_index + (i * chunk_blocks) as u64;
+        let mut keystream = [[0; 4 * N_B]; MAX_PARALLEL_BLOCKS];
+        let keystream = &mut keystream[..chunk.len().div_ceil(4 * N_B)];
+        for (block_index, counter_block) in (first_block_index..).zip(keystream.iter_mut()) {
+            *counter_block = layout.counter_block(initial_counter, block_index);
+        }
+        block_cipher.cipher_blocks(keystream);
+
+        chunk
+            .iter_mut()
+            .zip(keystream.as_flattened())
+            .for_each(|(byte, key_byte)| *byte ^= key_byte);
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes_block_cipher::AESBlockCipher;
+    use crate::modes::test_vectors::{executor, KEY_AES_128, KEY_AES_256, PLAIN_BLOCKS};
+
+    // NIST SP 800-38A, appendix F.5
+    const INITIAL_COUNTER: [u8; 16] = [
+        0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd, 0xfe,
+        0xff,
+    ];
+
+    const CIPHER_BLOCKS_AES_128: [[u8; 16]; 4] = [
+        [
+            0x87, 0x4d, 0x61, 0x91, 0xb6, 0x20, 0xe3, 0x26, 0x1b, 0xef, 0x68, 0x64, 0x99, 0x0d,
+            0xb6, 0xce,
+        ],
+        [
+            0x98, 0x06, 0xf6, 0x6b, 0x79, 0x70, 0xfd, 0xff, 0x86, 0x17, 0x18, 0x7b, 0xb9, 0xff,
+            0xfd, 0xff,
+        ],
+        [
+            0x5a, 0xe4, 0xdf, 0x3e, 0xdb, 0xd5, 0xd3, 0x5e, 0x5b, 0x4f, 0x09, 0x02, 0x0d, 0xb0,
+            0x3e, 0xab,
+        ],
+        [
+            0x1e, 0x03, 0x1d, 0xda, 0x2f, 0xbe, 0x03, 0xd1, 0x79, 0x21, 0x70, 0xa0, 0xf3, 0x00,
+            0x9c, 0xee,
+        ],
+    ];
+
+    const CIPHER_BLOCKS_AES_256: [[u8; 16]; 4] = [
+        [
+            0x60, 0x1e, 0xc3, 0x13, 0x77, 0x57, 0x89, 0xa5, 0xb7, 0xa7, 0xf5, 0x04, 0xbb, 0xf3,
+            0xd2, 0x28,
+        ],
+        [
+            0xf4, 0x43, 0xe3, 0xca, 0x4d, 0x62, 0xb5, 0x9a, 0xca, 0x84, 0xe9, 0x90, 0xca, 0xca,
+            0xf5, 0xc5,
+        ],
+        [
+            0x2b, 0x09, 0x30, 0xda, 0xa2, 0x3d, 0xe9, 0x4c, 0xe8, 0x70, 0x17, 0xba, 0x2d, 0x84,
+            0x98, 0x8d,
+        ],
+        [
+            0xdf, 0xc9, 0xc5, 0x8d, 0xb6, 0x7a, 0xad, 0xa6, 0x13, 0xc2, 0xdd, 0x08, 0x45, 0x79,
+            0x41, 0xa6,
+        ],
+    ];
+
+    fn apply(block_cipher: &dyn BlockCipher, layout: CounterLayout, offset: u64, data: &mut [u8]) {
+        apply_keystream(
+            block_cipher,
+            &executor(),
+            &INITIAL_COUNTER,
+            layout,
+            offset,
+            data,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_ctr_aes_128_encrypt_and_decrypt() {
+        let block_cipher = AESBlockCipher::new(&KEY_AES_128).unwrap();
+        let mut blocks = PLAIN_BLOCKS;
+
+        apply(
+            &block_cipher,
+            CounterLayout::Ctr128,
+            0,
+            blocks.as_flattened_mut(),
+        );
+        assert_eq!(blocks, CIPHER_BLOCKS_AES_128);
+
+        apply(
+            &block_cipher,
+            CounterLayout::Ctr128,
+            0,
+            blocks.as_flattened_mut(),
+        );
+        assert_eq!(blocks, PLAIN_BLOCKS);
+    }
+
+    #[test]
+    fn test_ctr_aes_256_encrypt_with_64_bit_counter() {
+        // The counter does not overflow its low 64 bits, so both layouts match the vectors
+        let block_cipher = AESBlockCipher::new(&KEY_AES_256).unwrap();
+        let mut blocks = PLAIN_BLOCKS;
+
+        apply(
+            &block_cipher,
+            CounterLayout::Ctr64,
+            0,
+            blocks.as_flattened_mut(),
+        );
+
+        assert_eq!(blocks, CIPHER_BLOCKS_AES_256);
+    }
+
+    #[test]
+    fn test_ctr_partial_final_block() {
+        let block_cipher = AESBlockCipher::new(&KEY_AES_128).unwrap();
+        let mut data = PLAIN_BLOCKS.as_flattened()[..37].to_vec();
+
+        apply(&block_cipher, CounterLayout::Ctr128, 0, &mut data);
+
+        assert_eq!(data, CIPHER_BLOCKS_AES_128.as_flattened()[..37]);
+    }
+
+    #[test]
+    fn test_ctr_random_access() {
+        let block_cipher = AESBlockCipher::new(&KEY_AES_128).unwrap();
+
+        for (start, end) in [(0, 5), (5, 16), (7, 40), (16, 64), (33, 34), (20, 60)] {
+            let mut data = PLAIN_BLOCKS.as_flattened()[start..end].to_vec();
+
+            apply(
+                &block_cipher,
+                CounterLayout::Ctr128,
+                start as u64,
+                &mut data,
+            );
+
+            assert_eq!(data, CIPHER_BLOCKS_AES_128.as_flattened()[start..end]);
+        }
+    }
+
+    #[test]
+    fn test_counter_layouts_on_overflow() {
+        let initial_counter = [0xff; 16];
+
+        let ctr_32 = CounterLayout::Ctr32.counter_block(&initial_counter, 1);
+        let ctr_64 = CounterLayout::Ctr64.counter_block(&initial_counter, 1);
+        let ctr_128 = CounterLayout::Ctr128.counter_block(&initial_counter, 1);
+
+        let mut expected_ctr_32 = [0xff; 16];
+        expected_ctr_32[12..].fill(0);
+        assert_eq!(ctr_32, expected_ctr_32);
+        let mut expected_ctr_64 = [0xff; 16];
+        expected_ctr_64[8..].fill(0);
+        assert_eq!(ctr_64, expected_ctr_64);
+        assert_eq!(ctr_128, [0; 16]);
+    }
+
+    #[test]
+    fn test_32_bit_counter_does_not_wrap_to_the_initial_counter() {
+        let block_cipher = AESBlockCipher::new(&KEY_AES_128).unwrap();
+        let last_block_index = u32::MAX as u64;
+        let last_block_offset = 16 * last_block_index;
+
+        // The last block before the wrap uses the counter just below the initial one
+        let last_counter_block =
+            CounterLayout::Ctr32.counter_block(&INITIAL_COUNTER, last_block_index);
+        let mut expected_counter_block = INITIAL_COUNTER;
+        expected_counter_block[12..].copy_from_slice(&0xfcfdfefe_u32.to_be_bytes());
+        assert_eq!(last_counter_block, expected_counter_block);
+
+        let mut data = [0; 16];
+        apply(
+            &block_cipher,
+            CounterLayout::Ctr32,
+            last_block_offset,
+            &mut data,
+        );
+        assert_eq!(data, block_cipher.cipher_block(&last_counter_block));
+
+        // One more byte would use the keystream of the first block again
+        let mut data = [0; 17];
+        let result = apply_keystream(
+            &block_cipher,
+            &executor(),
+            &INITIAL_COUNTER,
+            CounterLayout::Ctr32,
+            last_block_offset,
+            &mut data,
+        );
+        assert!(matches!(result, Err(Error::Unsupported(_))));
+        assert_eq!(data, [0; 17]);
+        assert!(CounterLayout::Ctr32
+            .check_range(last_block_offset + 16, 1)
+            .is_err());
+
+        assert!(CounterLayout::Ctr64
+            .check_range(last_block_offset, 17)
+            .is_ok());
+        assert!(CounterLayout::Ctr64.check_range(u64::MAX, 1).is_err());
+        assert!(CounterLayout::Ctr128.check_range(0, u64::MAX).is_ok());
+    }
+}
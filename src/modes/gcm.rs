@@ -0,0 +1,366 @@
+/*
+Galois/Counter Mode, as defined in NIST SP 800-38D.
+The plaintext is ciphered in CTR mode with a 32-bit counter, and the ciphertext and the
+associated data are authenticated with GHASH.
+ */
+use super::ctr::{self, CounterLayout};
+use super::ghash::Ghash;
+use crate::aes_block_cipher::{BlockCipher, N_B};
+use crate::concurrency::Executor;
+use crate::{Error, Result};
+
+// Recommended IV length, which avoids hashing the IV to get the pre-counter block
+pub const IV_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+/// Longest plaintext of a message, 2^39 - 256 bits as set by SP 800-38D section 5.2.1.1. Past it
+/// the 32-bit counter would wrap back to the pre-counter block, whose keystream masks the tag.
+pub const MAX_TEXT_LEN: u64 = (1 << 36) - 32;
+
+/// Authentication state of a message, which can be fed the ciphertext in several calls
+pub struct GcmState {
+    pre_counter_block: [u8; 4 * N_B],
+    ghash: Ghash,
+    aad_len: u64,
+    text_len: u64,
+}
+
+impl GcmState {
+    /// Creates the state for the message with the given IV and associated data.
+    /// The IV can have any non-zero length, although `IV_LEN` bytes are recommended.
+    pub fn new(block_cipher: &dyn BlockCipher, iv: &[u8], aad: &[u8]) -> Result<Self> {
+        if iv.is_empty() {
+            return Err(Error::InvalidConfig(
+                "the GCM IV cannot be empty".to_string(),
+            ));
+        }
+
+        let h = block_cipher.cipher_block(&[0; 4 * N_B]);
+        let pre_counter_block = Self::pre_counter_block(&h, iv);
+
+        let mut ghash = Ghash::new(&h);
+        ghash.update_padded(aad);
+
+        Ok(Self {
+            pre_counter_block,
+            ghash,
+            aad_len: aad.len() as u64,
+            text_len: 0,
+        })
+    }
+
+    fn pre_counter_block(h: &[u8; 4 * N_B], iv: &[u8]) -> [u8; 4 * N_B] {
+        if iv.len() == IV_LEN {
+            let mut pre_counter_block = [0; 4 * N_B];
+            pre_counter_block[..IV_LEN].copy_from_slice(iv);
+            pre_counter_block[4 * N_B - 1] = 1;
+            return pre_counter_block;
+        }
+
+        let mut len_block = [0; 4 * N_B];
+        len_block[2 * N_B..].copy_from_slice(&(8 * iv.len() as u64).to_be_bytes());
+
+        let mut ghash = Ghash::new(h);
+        ghash.update_padded(iv);
+        ghash.update_padded(&len_block);
+        ghash.finalize()
+    }
+
+    /// Returns the counter block used for the first block of the plaintext
+    pub fn initial_counter(&self) -> [u8; 4 * N_B] {
+        CounterLayout::Ctr32.counter_block(&self.pre_counter_block, 1)
+    }
+
+    /// Absorb the next part of the ciphertext. Only the last part may have a length that is not
+    /// a multiple of the block size.
+    pub fn update(&mut self, cipher_text: &[u8]) {
+        self.ghash.update_padded(cipher_text);
+        self.text_len += cipher_text.len() as u64;
+    }
+
+    pub fn finalize(mut self, block_cipher: &dyn BlockCipher) -> [u8; TAG_LEN] {
+        let mut len_block = [0; 4 * N_B];
+        len_block[..2 * N_B].copy_from_slice(&(8 * self.aad_len).to_be_bytes());
+        len_block[2 * N_B..].copy_from_slice(&(8 * self.text_len).to_be_bytes());
+        self.ghash.update_padded(&len_block);
+
+        let mut tag = block_cipher.cipher_block(&self.pre_counter_block);
+        super::xor_block(&mut tag, &self.ghash.finalize());
+        tag
+    }
+}
+
+/// Checks that a plaintext or ciphertext of `len` bytes is not longer than `MAX_TEXT_LEN`
+pub fn check_text_len(len: u64) -> Result<()> {
+    if len > MAX_TEXT_LEN {
+        return Err(Error::Unsupported(format!(
+            "GCM cannot cipher more than {} bytes",
+            MAX_TEXT_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// Cipher `data` in place and return its authentication tag, which also covers `aad`.
+pub fn seal(
+    block_cipher: &dyn BlockCipher,
+    executor: &dyn Executor,
+    iv: &[u8],
+    aad: &[u8],
+    data: &mut [u8],
+) -> Result<[u8; TAG_LEN]> {
+    check_text_len(data.len() as u64)?;
+    let mut state = GcmState::new(block_cipher, iv, aad)?;
+    ctr::apply_keystream(
+        block_cipher,
+        executor,
+        &state.initial_counter(),
+        CounterLayout::Ctr32,
+        0,
+        data,
+    )?;
+    state.update(data);
+    Ok(state.finalize(block_cipher))
+}
+
+/// Verify the tag of `data` and `aad`, and only decipher `data` in place if it matches.
+pub fn open(
+    block_cipher: &dyn BlockCipher,
+    executor: &dyn Executor,
+    iv: &[u8],
+    aad: &[u8],
+    data: &mut [u8],
+    tag: &[u8],
+) -> Result<()> {
+    check_text_len(data.len() as u64)?;
+    let mut state = GcmState::new(block_cipher, iv, aad)?;
+    let initial_counter = state.initial_counter();
+    state.update(data);
+
+    if !tags_match(&state.finalize(block_cipher), tag) {
+        return Err(Error::AuthenticationFailed);
+    }
+
+    ctr::apply_keystream(
+        block_cipher,
+        executor,
+        &initial_counter,
+        CounterLayout::Ctr32,
+        0,
+        data,
+    )?;
+    Ok(())
+}
+
+/// Compare the tags in constant time, so an attacker cannot learn how many bytes match
+pub fn tags_match(expected_tag: &[u8], tag: &[u8]) -> bool {
+    expected_tag.len() == tag.len()
+        && expected_tag
+            .iter()
+            .zip(tag)
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes_block_cipher::AESBlockCipher;
+    use crate::modes::test_vectors::{executor, from_hex};
+
+    // Test cases from "The Galois/Counter Mode of Operation (GCM)", McGrew and Viega
+    const KEY: &str = "feffe9928665731c6d6a8f9467308308";
+    const PLAIN_TEXT: &str = "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+                              1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b391aafd255";
+    const AAD: &str = "feedfacedeadbeeffeedfacedeadbeefabaddad2";
+
+    fn check_vector(
+        key: &str,
+        iv: &str,
+        plain_text: &str,
+        aad: &str,
+        cipher_text: &str,
+        tag: &str,
+    ) {
+        let block_cipher = AESBlockCipher::new(&from_hex(key)).unwrap();
+        let executor = executor();
+        let mut data = from_hex(plain_text);
+
+        let computed_tag = seal(
+            &block_cipher,
+            &executor,
+            &from_hex(iv),
+            &from_hex(aad),
+            &mut data,
+        );
+
+        assert_eq!(data, from_hex(cipher_text));
+        assert_eq!(computed_tag.unwrap().to_vec(), from_hex(tag));
+
+        open(
+            &block_cipher,
+            &executor,
+            &from_hex(iv),
+            &from_hex(aad),
+            &mut data,
+            &from_hex(tag),
+        )
+        .unwrap();
+        assert_eq!(data, from_hex(plain_text));
+    }
+
+    #[test]
+    fn test_gcm_test_case_1() {
+        check_vector(
+            "00000000000000000000000000000000",
+            "000000000000000000000000",
+            "",
+            "",
+            "",
+            "58e2fccefa7e3061367f1d57a4e7455a",
+        );
+    }
+
+    #[test]
+    fn test_gcm_test_case_2() {
+        check_vector(
+            "00000000000000000000000000000000",
+            "000000000000000000000000",
+            "00000000000000000000000000000000",
+            "",
+            "0388dace60b6a392f328c2b971b2fe78",
+            "ab6e47d42cec13bdf53a67b21257bddf",
+        );
+    }
+
+    #[test]
+    fn test_gcm_test_case_3() {
+        check_vector(
+            KEY,
+            "cafebabefacedbaddecaf888",
+            PLAIN_TEXT,
+            "",
+            "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+             21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091473f5985",
+            "4d5c2af327cd64a62cf35abd2ba6fab4",
+        );
+    }
+
+    #[test]
+    fn test_gcm_test_case_4_with_aad_and_partial_block() {
+        check_vector(
+            KEY,
+            "cafebabefacedbaddecaf888",
+            &PLAIN_TEXT[..120],
+            AAD,
+            "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+             21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091",
+            "5bc94fbc3221a5db94fae95ae7121a47",
+        );
+    }
+
+    #[test]
+    fn test_gcm_test_case_5_with_64_bit_iv() {
+        check_vector(
+            KEY,
+            "cafebabefacedbad",
+            &PLAIN_TEXT[..120],
+            AAD,
+            "61353b4c2806934a777ff51fa22a4755699b2a714fcdc6f83766e5f97b6c7423\
+             73806900e49f24b22b097544d4896b424989b5e1ebac0f07c23f4598",
+            "3612d2e79e3b0785561be14aaca2fccb",
+        );
+    }
+
+    #[test]
+    fn test_gcm_test_case_6_with_480_bit_iv() {
+        check_vector(
+            KEY,
+            "9313225df88406e555909c5aff5269aa6a7a9538534f7da1e4c303d2a318a728\
+             c3c0c95156809539fcf0e2429a6b525416aedbf5a0de6a57a637b39b",
+            &PLAIN_TEXT[..120],
+            AAD,
+            "8ce24998625615b603a033aca13fb894be9112a5c3a211a8ba262a3cca7e2ca7\
+             01e4a9a4fba43c90ccdcb281d48c7c6fd62875d2aca417034c34aee5",
+            "619cc5aefffe0bfa462af43c1699d050",
+        );
+    }
+
+    #[test]
+    fn test_gcm_test_case_16_aes_256() {
+        check_vector(
+            "feffe9928665731c6d6a8f9467308308feffe9928665731c6d6a8f9467308308",
+            "cafebabefacedbaddecaf888",
+            &PLAIN_TEXT[..120],
+            AAD,
+            "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa\
+             8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662",
+            "76fc6ece0f4e1768cddf8853bb2d551b",
+        );
+    }
+
+    #[test]
+    fn test_gcm_rejects_modified_data() {
+        let block_cipher = AESBlockCipher::new(&from_hex(KEY)).unwrap();
+        let executor = executor();
+        let iv = from_hex("cafebabefacedbaddecaf888");
+        let aad = from_hex(AAD);
+        let mut data = from_hex(PLAIN_TEXT);
+        let tag = seal(&block_cipher, &executor, &iv, &aad, &mut data).unwrap();
+
+        let mut modified_data = data.clone();
+        modified_data[10] ^= 1;
+        let result = open(
+            &block_cipher,
+            &executor,
+            &iv,
+            &aad,
+            &mut modified_data,
+            &tag,
+        );
+        assert!(result.is_err());
+        // Nothing is deciphered when the tag does not match
+        assert_ne!(modified_data[..10], from_hex(PLAIN_TEXT)[..10]);
+
+        let mut modified_tag = tag;
+        modified_tag[0] ^= 1;
+        let result = open(
+            &block_cipher,
+            &executor,
+            &iv,
+            &aad,
+            &mut data,
+            &modified_tag,
+        );
+        assert!(result.is_err());
+
+        let result = open(&block_cipher, &executor, &iv, &[], &mut data, &tag);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_text_len_stops_before_the_counter_wraps() {
+        let block_cipher = AESBlockCipher::new(&from_hex(KEY)).unwrap();
+        for iv in ["cafebabefacedbaddecaf888", "cafebabefacedbad"] {
+            let state = GcmState::new(&block_cipher, &from_hex(iv), &[]).unwrap();
+            let initial_counter = state.initial_counter();
+            let max_blocks = MAX_TEXT_LEN / 16;
+
+            // Neither the blocks up to the limit nor the one after them use the pre-counter
+            // block, but the next one does
+            for block_index in [0, max_blocks - 1, max_blocks] {
+                let counter_block =
+                    CounterLayout::Ctr32.counter_block(&initial_counter, block_index);
+                assert_ne!(counter_block, state.pre_counter_block);
+            }
+            let counter_block =
+                CounterLayout::Ctr32.counter_block(&initial_counter, max_blocks + 1);
+            assert_eq!(counter_block, state.pre_counter_block);
+        }
+
+        assert!(check_text_len(MAX_TEXT_LEN).is_ok());
+        assert!(matches!(
+            check_text_len(MAX_TEXT_LEN + 1),
+            Err(Error::Unsupported(_))
+        ));
+    }
+}
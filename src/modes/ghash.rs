@@ -0,0 +1,82 @@
+/*
+GHASH function over GF(2^128), as defined in NIST SP 800-38D section 6.4.
+Blocks are interpreted as big-endian integers, so the bit order of the specification
+(where the first bit is the coefficient of x^0) maps to the most significant bit.
+ */
+use crate::aes_block_cipher::N_B;
+
+// x^128 + x^7 + x^2 + x + 1, in the reflected bit order
+const R: u128 = 0xe1 << 120;
+
+pub struct Ghash {
+    h: u128,
+    y: u128,
+}
+
+impl Ghash {
+    /// Creates a GHASH instance keyed with the hash subkey `h`
+    pub fn new(h: &[u8; 4 * N_B]) -> Self {
+        Self {
+            h: u128::from_be_bytes(*h),
+            y: 0,
+        }
+    }
+
+    /// Absorb the data, padded with zeros up to a multiple of the block size
+    pub fn update_padded(&mut self, data: &[u8]) {
+        for chunk in data.chunks(4 * N_B) {
+            let mut block = [0; 4 * N_B];
+            block[..chunk.len()].copy_from_slice(chunk);
+            self.y = gf_mul(self.y ^ u128::from_be_bytes(block), self.h);
+        }
+    }
+
+    pub fn finalize(self) -> [u8; 4 * N_B] {
+        self.y.to_be_bytes()
+    }
+}
+
+/// Multiplication in GF(2^128), following algorithm 1 of SP 800-38D.
+/// Masks are used instead of branches, so the running time does not depend on the operands.
+pub fn gf_mul(x: u128, y: u128) -> u128 {
+    let mut z = 0;
+    let mut v = y;
+    for i in (0..128).rev() {
+        let x_bit = (x >> i) & 1;
+        z ^= v & x_bit.wrapping_neg();
+        let v_lsb = v & 1;
+        v = (v >> 1) ^ (R & v_lsb.wrapping_neg());
+    }
+    z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gf_mul_by_one() {
+        // The multiplicative identity is x^0, which is the most significant bit
+        let one = 1 << 127;
+        let value = 0x66e94bd4ef8a2c3b884cfa59ca342b2e;
+        assert_eq!(gf_mul(value, one), value);
+        assert_eq!(gf_mul(one, value), value);
+    }
+
+    #[test]
+    fn test_ghash_gcm_test_case_2() {
+        // Test case 2 of the GCM specification: H = E(0^128, 0^128) and C = 0388dace...
+        let h = 0x66e94bd4ef8a2c3b884cfa59ca342b2eu128.to_be_bytes();
+        let cipher_text = 0x0388dace60b6a392f328c2b971b2fe78u128.to_be_bytes();
+        let len_block = 0x80u128.to_be_bytes();
+
+        let mut ghash = Ghash::new(&h);
+        ghash.update_padded(&cipher_text);
+        ghash.update_padded(&len_block);
+
+        assert_eq!(
+            ghash.finalize(),
+            0xf38cbb1ad69223dcc3457ae5b6b0f885u128.to_be_bytes()
+        );
+    }
+}
@@ -0,0 +1,84 @@
+pub mod cbc;
+pub mod ctr;
+pub mod gcm;
+mod ghash;
+
+#[cfg(test)]
+mod test_vectors;
+
+use crate::aes_block_cipher::N_B;
+use ctr::CounterLayout;
+
+/// Block cipher modes of operation supported by `AESCipher`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Ecb,
+    Cbc,
+    Ctr(CounterLayout),
+    Gcm,
+}
+
+impl Mode {
+    /// Name of the mode, as used in the configuration and the metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Ecb => "ecb",
+            Mode::Cbc => "cbc",
+            Mode::Ctr(CounterLayout::Ctr128) => "ctr",
+            Mode::Ctr(CounterLayout::Ctr64) => "ctr64",
+            Mode::Ctr(CounterLayout::Ctr32) => "ctr32",
+            Mode::Gcm => "gcm",
+        }
+    }
+
+    /// Whether the plaintext has to be padded to a multiple of the block size
+    pub fn needs_padding(&self) -> bool {
+        match self {
+            Mode::Ecb | Mode::Cbc => true,
+            Mode::Ctr(_) | Mode::Gcm => false,
+        }
+    }
+
+    /// Whether every block is ciphered and deciphered on its own, without depending on the
+    /// others, so that any part of a file can be processed separately
+    pub fn has_independent_blocks(&self) -> bool {
+        match self {
+            Mode::Ecb | Mode::Ctr(_) => true,
+            Mode::Cbc | Mode::Gcm => false,
+        }
+    }
+
+    /// Returns a fresh random IV, initial counter block or nonce for the mode, which is empty
+    /// for ECB
+    pub fn random_iv(&self) -> Vec<u8> {
+        match self {
+            Mode::Ecb => Vec::new(),
+            Mode::Cbc => rand::random::<[u8; 4 * N_B]>().to_vec(),
+            Mode::Ctr(layout) => layout.random_initial_counter().to_vec(),
+            Mode::Gcm => rand::random::<[u8; gcm::IV_LEN]>().to_vec(),
+        }
+    }
+}
+
+impl std::str::FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ecb" => Ok(Mode::Ecb),
+            "cbc" => Ok(Mode::Cbc),
+            "ctr" | "ctr128" => Ok(Mode::Ctr(CounterLayout::Ctr128)),
+            "ctr64" => Ok(Mode::Ctr(CounterLayout::Ctr64)),
+            "ctr32" => Ok(Mode::Ctr(CounterLayout::Ctr32)),
+            "gcm" => Ok(Mode::Gcm),
+            _ => Err(format!("Unknown mode of operation: {}", s)),
+        }
+    }
+}
+
+pub fn xor_block(block: &mut [u8; 4 * N_B], other: &[u8; 4 * N_B]) {
+    block
+        .iter_mut()
+        .zip(other.iter())
+        .for_each(|(byte, other_byte)| *byte ^= other_byte);
+}
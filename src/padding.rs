@@ -0,0 +1,232 @@
+/*
+Padding schemes used to extend the last block of the plaintext to the block size, for the modes
+of operation that only work with whole blocks (ECB and CBC)
+ */
+use crate::aes_block_cipher::N_B;
+
+const BLOCK_SIZE: usize = 4 * N_B;
+
+pub trait Padding: Send + Sync {
+    /// Pad the last block of the input, which has `len` bytes of data (less than the block
+    /// size). Return `false` if the block does not have to be written, which can only happen
+    /// when `len` is zero, or an error if the input cannot be padded with this scheme.
+    fn pad(&self, block: &mut [u8; BLOCK_SIZE], len: usize) -> Result<bool, String>;
+
+    /// Return the number of data bytes in the last block of the input, or an error if its
+    /// padding is not valid.
+    fn unpad(&self, block: &[u8; BLOCK_SIZE]) -> Result<usize, String>;
+}
+
+/// PKCS#7 (RFC 5652): every padding byte is the number of padding bytes.
+/// A whole padding block is added when the input is already a multiple of the block size.
+pub struct Pkcs7;
+
+/// ISO/IEC 7816-4: a 0x80 byte followed by zeros.
+pub struct Iso7816;
+
+/// ANSI X9.23: zeros followed by a byte with the number of padding bytes.
+pub struct AnsiX923;
+
+/// Zeros up to the end of the block. Trailing zero bytes of the data cannot be told apart from
+/// the padding, so it only round trips data that does not end with 0x00 (such as text).
+pub struct ZeroPadding;
+
+/// No padding: the input has to be a multiple of the block size.
+pub struct NoPadding;
+
+impl Padding for Pkcs7 {
+    fn pad(&self, block: &mut [u8; BLOCK_SIZE], len: usize) -> Result<bool, String> {
+        let padding_len = BLOCK_SIZE - len;
+        block[len..].fill(padding_len as u8);
+        Ok(true)
+    }
+
+    fn unpad(&self, block: &[u8; BLOCK_SIZE]) -> Result<usize, String> {
+        let padding_len = block[BLOCK_SIZE - 1] as usize;
+        if padding_len == 0
+            || padding_len > BLOCK_SIZE
+            || block[BLOCK_SIZE - padding_len..]
+                .iter()
+                .any(|&byte| byte as usize != padding_len)
+        {
+            return Err("Invalid PKCS#7 padding".to_string());
+        }
+        Ok(BLOCK_SIZE - padding_len)
+    }
+}
+
+impl Padding for Iso7816 {
+    fn pad(&self, block: &mut [u8; BLOCK_SIZE], len: usize) -> Result<bool, String> {
+        block[len] = 0x80;
+        block[len + 1..].fill(0);
+        Ok(true)
+    }
+
+    fn unpad(&self, block: &[u8; BLOCK_SIZE]) -> Result<usize, String> {
+        match block.iter().rposition(|&byte| byte != 0) {
+            Some(pos) if block[pos] == 0x80 => Ok(pos),
+            _ => Err("Invalid ISO/IEC 7816-4 padding".to_string()),
+        }
+    }
+}
+
+impl Padding for AnsiX923 {
+    fn pad(&self, block: &mut [u8; BLOCK_SIZE], len: usize) -> Result<bool, String> {
+        let padding_len = BLOCK_SIZE - len;
+        block[len..BLOCK_SIZE - 1].fill(0);
+        block[BLOCK_SIZE - 1] = padding_len as u8;
+        Ok(true)
+    }
+
+    fn unpad(&self, block: &[u8; BLOCK_SIZE]) -> Result<usize, String> {
+        let padding_len = block[BLOCK_SIZE - 1] as usize;
+        if padding_len == 0
+            || padding_len > BLOCK_SIZE
+            || block[BLOCK_SIZE - padding_len..BLOCK_SIZE - 1]
+                .iter()
+                .any(|&byte| byte != 0)
+        {
+            return Err("Invalid ANSI X9.23 padding".to_string());
+        }
+        Ok(BLOCK_SIZE - padding_len)
+    }
+}
+
+impl Padding for ZeroPadding {
+    fn pad(&self, block: &mut [u8; BLOCK_SIZE], len: usize) -> Result<bool, String> {
+        block[len..].fill(0);
+        Ok(len > 0)
+    }
+
+    fn unpad(&self, block: &[u8; BLOCK_SIZE]) -> Result<usize, String> {
+        Ok(block
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(0, |pos| pos + 1))
+    }
+}
+
+impl Padding for NoPadding {
+    fn pad(&self, _block: &mut [u8; BLOCK_SIZE], len: usize) -> Result<bool, String> {
+        if len > 0 {
+            return Err(format!(
+                "The input length is not a multiple of {} bytes, and no padding was selected",
+                BLOCK_SIZE
+            ));
+        }
+        Ok(false)
+    }
+
+    fn unpad(&self, _block: &[u8; BLOCK_SIZE]) -> Result<usize, String> {
+        Ok(BLOCK_SIZE)
+    }
+}
+
+/// Padding schemes that can be selected in the configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaddingScheme {
+    Pkcs7,
+    Iso7816,
+    AnsiX923,
+    Zero,
+    None,
+}
+
+impl PaddingScheme {
+    pub fn padding(&self) -> &'static dyn Padding {
+        match self {
+            PaddingScheme::Pkcs7 => &Pkcs7,
+            PaddingScheme::Iso7816 => &Iso7816,
+            PaddingScheme::AnsiX923 => &AnsiX923,
+            PaddingScheme::Zero => &ZeroPadding,
+            PaddingScheme::None => &NoPadding,
+        }
+    }
+}
+
+impl std::str::FromStr for PaddingScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pkcs7" => Ok(PaddingScheme::Pkcs7),
+            "iso7816" => Ok(PaddingScheme::Iso7816),
+            "ansix923" => Ok(PaddingScheme::AnsiX923),
+            "zero" => Ok(PaddingScheme::Zero),
+            "none" => Ok(PaddingScheme::None),
+            _ => Err(format!("Unknown padding scheme: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pad(padding: &dyn Padding, data: &[u8]) -> Option<[u8; BLOCK_SIZE]> {
+        let mut block = [0xaa; BLOCK_SIZE];
+        block[..data.len()].copy_from_slice(data);
+        match padding.pad(&mut block, data.len()).unwrap() {
+            true => Some(block),
+            false => None,
+        }
+    }
+
+    #[test]
+    fn test_pkcs7() {
+        let block = pad(&Pkcs7, &[1, 2, 3]).unwrap();
+        assert_eq!(block[..3], [1, 2, 3]);
+        assert_eq!(block[3..], [13; 13]);
+        assert_eq!(Pkcs7.unpad(&block), Ok(3));
+
+        let block = pad(&Pkcs7, &[]).unwrap();
+        assert_eq!(block, [16; 16]);
+        assert_eq!(Pkcs7.unpad(&block), Ok(0));
+    }
+
+    #[test]
+    fn test_pkcs7_invalid_padding() {
+        let mut block = pad(&Pkcs7, &[1, 2, 3]).unwrap();
+        block[5] = 12;
+        assert!(Pkcs7.unpad(&block).is_err());
+        assert!(Pkcs7.unpad(&[0; 16]).is_err());
+        assert!(Pkcs7.unpad(&[17; 16]).is_err());
+    }
+
+    #[test]
+    fn test_iso7816() {
+        let block = pad(&Iso7816, &[0, 0x80, 0]).unwrap();
+        assert_eq!(block[..4], [0, 0x80, 0, 0x80]);
+        assert_eq!(block[4..], [0; 12]);
+        assert_eq!(Iso7816.unpad(&block), Ok(3));
+
+        assert!(Iso7816.unpad(&[0; 16]).is_err());
+        assert!(Iso7816.unpad(&[1; 16]).is_err());
+    }
+
+    #[test]
+    fn test_ansi_x923() {
+        let block = pad(&AnsiX923, &[7; 10]).unwrap();
+        assert_eq!(block[10..], [0, 0, 0, 0, 0, 6]);
+        assert_eq!(AnsiX923.unpad(&block), Ok(10));
+
+        let mut block = block;
+        block[11] = 1;
+        assert!(AnsiX923.unpad(&block).is_err());
+    }
+
+    #[test]
+    fn test_zero_padding() {
+        let block = pad(&ZeroPadding, &[1, 2, 3]).unwrap();
+        assert_eq!(block[3..], [0; 13]);
+        assert_eq!(ZeroPadding.unpad(&block), Ok(3));
+        assert_eq!(pad(&ZeroPadding, &[]), None);
+    }
+
+    #[test]
+    fn test_no_padding() {
+        assert_eq!(pad(&NoPadding, &[]), None);
+        assert!(NoPadding.pad(&mut [0; 16], 5).is_err());
+        assert_eq!(NoPadding.unpad(&[0; 16]), Ok(16));
+    }
+}
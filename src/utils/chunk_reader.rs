@@ -1,3 +1,5 @@
+use crate::padding::Padding;
+use crate::{Error, Result};
 use std::io::{BufReader, Read};
 
 pub struct ChunkReader<T>
@@ -6,79 +8,87 @@ where
 {
     input: BufReader<T>,
     chunk_size: usize,
-    with_padding: bool,
+    padding: Option<&'static dyn Padding>,
+    last_chunk_size: usize,
+    finished: bool,
 }
 
 impl<T> ChunkReader<T>
 where
     T: Read,
 {
-    pub fn new(input: T, chunk_size: usize, with_padding: bool) -> Self {
+    pub fn new(input: T, chunk_size: usize, padding: Option<&'static dyn Padding>) -> Self {
         ChunkReader {
             input: BufReader::new(input),
             chunk_size,
-            with_padding,
+            padding,
+            last_chunk_size: 0,
+            finished: false,
         }
     }
 
     /// Read at most `chunk_size` bytes from the input, and place them in the buffer.
+    /// If a padding scheme was given, the last chunk is padded with it, which may add an extra
+    /// chunk when the input is a multiple of `chunk_size`.
     /// Return the number of chunks filled.
-    pub fn read_chunks(
-        &mut self,
-        chunks_amount: usize,
-        buffer: &mut [[u8; 16]],
-    ) -> std::io::Result<usize> {
+    pub fn read_chunks(&mut self, chunks_amount: usize, buffer: &mut [[u8; 16]]) -> Result<usize> {
         let mut chunks_filled = 0;
-        while chunks_filled < chunks_amount {
+        while chunks_filled < chunks_amount && !self.finished {
             let chunk = &mut buffer[chunks_filled];
-            match self.fill_chunk(chunk) {
-                Ok(0) => return Ok(chunks_filled),
-                Ok(n) => {
-                    chunks_filled += 1;
-                    if n < self.chunk_size {
-                        return Ok(chunks_filled);
-                    }
-                }
-                Err(e) => return Err(e),
+            let bytes_read = self.fill_chunk(chunk)?;
+            if bytes_read == self.chunk_size {
+                chunks_filled += 1;
+                self.last_chunk_size = bytes_read;
+                continue;
+            }
+
+            self.finished = true;
+            let write_chunk = match self.padding {
+                Some(padding) => padding
+                    .pad(chunk, bytes_read)
+                    .map_err(Error::InvalidPadding)?,
+                None => bytes_read > 0,
+            };
+            if write_chunk {
+                chunks_filled += 1;
+                self.last_chunk_size = bytes_read;
             }
         }
         Ok(chunks_filled)
     }
 
+    /// Return the number of bytes read into the last chunk filled by `read_chunks`, not
+    /// counting any padding. It is only less than `chunk_size` at the end of the input.
+    pub fn last_chunk_size(&self) -> usize {
+        self.last_chunk_size
+    }
+
     /// Fill the buffer with the next chunk of data from the input.
     /// Returns the number of bytes read, or an error if the read operation fails.
     fn fill_chunk(&mut self, buffer: &mut [u8; 16]) -> std::io::Result<usize> {
         let mut bytes_read = 0;
         while bytes_read < self.chunk_size {
             match self.input.read(&mut buffer[bytes_read..]) {
-                Ok(0) => {
-                    if self.with_padding {
-                        self.apply_null_padding(bytes_read, buffer);
-                    }
-                    return Ok(bytes_read);
-                }
+                Ok(0) => return Ok(bytes_read),
                 Ok(n) => bytes_read += n,
                 Err(e) => return Err(e),
             }
         }
         Ok(bytes_read)
     }
-
-    fn apply_null_padding(&self, from: usize, buffer: &mut [u8; 16]) {
-        (from..self.chunk_size).for_each(|i| buffer[i] = 0);
-    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::padding::{Pkcs7, ZeroPadding};
 
     use std::io::Cursor;
 
     #[test]
     fn test_read_one_chunk_exact_size() {
         let input = Cursor::new(vec![54u8; 16]);
-        let mut reader = ChunkReader::new(input, 16, true);
+        let mut reader = ChunkReader::new(input, 16, Some(&ZeroPadding));
         let mut buffer = [[0u8; 16]; 1];
         let chunks_filled = reader.read_chunks(1, &mut buffer).unwrap();
         assert_eq!(chunks_filled, 1);
@@ -88,7 +98,7 @@ mod tests {
     #[test]
     fn test_read_one_chunk_partial_size() {
         let input = Cursor::new(vec![54u8; 8]);
-        let mut reader = ChunkReader::new(input, 16, true);
+        let mut reader = ChunkReader::new(input, 16, Some(&ZeroPadding));
         let mut buffer = [[0u8; 16]; 1];
         let chunks_filled = reader.read_chunks(1, &mut buffer).unwrap();
         assert_eq!(chunks_filled, 1);
@@ -108,7 +118,7 @@ mod tests {
         (16..32).for_each(|i| vec[i] = 76u8);
 
         let input = Cursor::new(vec);
-        let mut reader = ChunkReader::new(input, 16, true);
+        let mut reader = ChunkReader::new(input, 16, Some(&ZeroPadding));
         let mut buffer = [[0u8; 16]; 2];
         let chunks_filled = reader.read_chunks(2, &mut buffer).unwrap();
         assert_eq!(chunks_filled, 2);
@@ -124,7 +134,7 @@ mod tests {
         (16..24).for_each(|i| vec[i] = 98u8);
 
         let input = Cursor::new(vec);
-        let mut reader = ChunkReader::new(input, 16, true);
+        let mut reader = ChunkReader::new(input, 16, Some(&ZeroPadding));
         let mut buffer = [[0u8; 16]; 2];
         let chunks_filled = reader.read_chunks(2, &mut buffer).unwrap();
         assert_eq!(chunks_filled, 2);
@@ -147,11 +157,33 @@ mod tests {
     #[test]
     fn test_read_more_than_available() {
         let input = Cursor::new(vec![54u8; 16]);
-        let mut reader = ChunkReader::new(input, 16, true);
+        let mut reader = ChunkReader::new(input, 16, Some(&ZeroPadding));
         let mut buffer = [[0u8; 16]; 2];
         let chunks_filled = reader.read_chunks(2, &mut buffer).unwrap();
         assert_eq!(chunks_filled, 1);
         assert_eq!(buffer[0], [54u8; 16]);
         assert_eq!(buffer[1], [0u8; 16]);
     }
+
+    #[test]
+    fn test_last_chunk_size_without_padding() {
+        let input = Cursor::new(vec![54u8; 20]);
+        let mut reader = ChunkReader::new(input, 16, None);
+        let mut buffer = [[0u8; 16]; 2];
+        let chunks_filled = reader.read_chunks(2, &mut buffer).unwrap();
+        assert_eq!(chunks_filled, 2);
+        assert_eq!(reader.last_chunk_size(), 4);
+    }
+
+    #[test]
+    fn test_pkcs7_padding_adds_a_block_to_exact_size_input() {
+        let input = Cursor::new(vec![54u8; 16]);
+        let mut reader = ChunkReader::new(input, 16, Some(&Pkcs7));
+        let mut buffer = [[0u8; 16]; 1];
+        assert_eq!(reader.read_chunks(1, &mut buffer).unwrap(), 1);
+        assert_eq!(buffer[0], [54u8; 16]);
+        assert_eq!(reader.read_chunks(1, &mut buffer).unwrap(), 1);
+        assert_eq!(buffer[0], [16u8; 16]);
+        assert_eq!(reader.read_chunks(1, &mut buffer).unwrap(), 0);
+    }
 }
@@ -1,3 +1,5 @@
+use crate::padding::Padding;
+use crate::{Error, Result};
 use std::io::{BufWriter, Write};
 
 pub struct ChunkWriter<T>
@@ -5,22 +7,24 @@ where
     T: Write,
 {
     output: BufWriter<T>,
-    remove_padding: bool,
+    padding: Option<&'static dyn Padding>,
+    pending_chunk: Option<[u8; 16]>,
 }
 
 impl<T> ChunkWriter<T>
 where
     T: Write,
 {
-    pub fn new(output: T, remove_padding: bool) -> Self {
+    pub fn new(output: T, padding: Option<&'static dyn Padding>) -> Self {
         ChunkWriter {
             output: BufWriter::new(output),
-            remove_padding,
+            padding,
+            pending_chunk: None,
         }
     }
 
-    /// Write the chunks to the output, removing any null padding if `remove_padding` is set to
-    /// `true`.
+    /// Write the chunks to the output. If a padding scheme was given, the last chunk is held
+    /// back until `finish` is called, since it is the only one that contains padding.
     /// Return `Ok(())` if the write operation is successful, or an error if it fails to write
     /// any of the chunks.
     pub fn write_chunks(&mut self, chunks: &[[u8; 16]]) -> std::io::Result<()> {
@@ -30,21 +34,49 @@ where
         Ok(())
     }
 
-    /// Write the chunk to the output, removing any null padding if `remove_padding` is set to
-    /// `true`.
+    /// Write the chunk to the output, or hold it back if it may be the padded one.
     /// Return `Ok(())` if the write operation is successful, or an error if it fails to write
     /// the chunk.
     fn write_chunk(&mut self, chunk: &[u8; 16]) -> std::io::Result<()> {
-        if self.remove_padding {
-            self.write_chunk_without_padding(chunk)
-        } else {
-            self.output.write_all(chunk)
+        if self.padding.is_none() {
+            return self.output.write_all(chunk);
+        }
+        match self.pending_chunk.replace(*chunk) {
+            Some(previous_chunk) => self.output.write_all(&previous_chunk),
+            None => Ok(()),
         }
     }
 
-    fn write_chunk_without_padding(&mut self, chunk: &[u8; 16]) -> std::io::Result<()> {
-        let padding_pos = chunk.iter().position(|&byte| byte == 0).unwrap_or(16);
-        self.output.write_all(&chunk[..padding_pos])
+    /// Write the bytes to the output as they are, for data that does not fill a whole chunk.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.output.write_all(bytes)
+    }
+
+    /// Write the last chunk without its padding and flush the output.
+    /// Return an error if the padding is not valid, which usually means that the data was
+    /// deciphered with the wrong key or settings.
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some(padding) = self.padding {
+            let last_chunk = self.pending_chunk.take();
+            let data_len = unpadded_len(padding, last_chunk.as_ref())?;
+            if let Some(last_chunk) = last_chunk {
+                self.output.write_all(&last_chunk[..data_len])?;
+            }
+        }
+        Ok(self.output.flush()?)
+    }
+}
+
+/// Number of bytes of the last chunk of the input that are data and not padding, or 0 if the
+/// input is empty. Return an error if the padding is not valid.
+pub fn unpadded_len(padding: &dyn Padding, last_chunk: Option<&[u8; 16]>) -> Result<usize> {
+    match last_chunk {
+        Some(last_chunk) => padding.unpad(last_chunk).map_err(Error::InvalidPadding),
+        // Schemes that pad empty inputs always produce at least one chunk
+        None if padding.pad(&mut [0; 16], 0).unwrap_or(false) => Err(Error::InvalidPadding(
+            "the input is empty, so it is missing its padding".to_string(),
+        )),
+        None => Ok(0),
     }
 }
 
@@ -56,3 +88,43 @@ where
         self.output.flush().expect("Failed to flush the output");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::padding::Pkcs7;
+
+    #[test]
+    fn test_write_chunks_removes_padding_of_the_last_chunk() {
+        let mut output = Vec::new();
+        {
+            let mut writer = ChunkWriter::new(&mut output, Some(&Pkcs7));
+            let mut last_chunk = [0u8; 16];
+            last_chunk[8..].fill(8);
+            writer.write_chunks(&[[0u8; 16]]).unwrap();
+            writer.write_chunks(&[[1u8; 16], last_chunk]).unwrap();
+            writer.finish().unwrap();
+        }
+        assert_eq!(output.len(), 40);
+        assert_eq!(output[..16], [0u8; 16]);
+        assert_eq!(output[16..32], [1u8; 16]);
+        assert_eq!(output[32..], [0u8; 8]);
+    }
+
+    #[test]
+    fn test_finish_with_invalid_padding() {
+        let mut writer = ChunkWriter::new(Vec::new(), Some(&Pkcs7));
+        writer.write_chunks(&[[1u8; 16], [0u8; 16]]).unwrap();
+        let error = writer.finish().unwrap_err();
+        assert!(matches!(error, Error::InvalidPadding(_)));
+    }
+
+    #[test]
+    fn test_finish_without_chunks_and_padding() {
+        let mut writer = ChunkWriter::new(Vec::new(), Some(&Pkcs7));
+        assert!(writer.finish().is_err());
+
+        let mut writer = ChunkWriter::new(Vec::new(), None);
+        assert!(writer.finish().is_ok());
+    }
+}
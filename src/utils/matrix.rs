@@ -13,9 +13,9 @@ impl Matrix {
     #[cfg(test)]
     pub fn new_from_data(data: [[u8; 4]; 4]) -> Self {
         let mut result = 0;
-        for i in 0..4 {
-            for j in 0..4 {
-                result |= (data[i][j] as u128) << (8 * (i + 4 * j));
+        for (i, row) in data.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                result |= (*value as u128) << (8 * (i + 4 * j));
             }
         }
         Self { data: result }
@@ -42,8 +42,8 @@ impl Matrix {
     #[cfg(test)]
     pub fn get_row(&self, row: usize) -> [u8; 4] {
         let mut result = [0; 4];
-        for i in 0..4 {
-            result[i] = self.get(row, i);
+        for (i, value) in result.iter_mut().enumerate() {
+            *value = self.get(row, i);
         }
         result
     }
@@ -51,8 +51,8 @@ impl Matrix {
     pub fn get_cols(&self) -> impl Iterator<Item = [u8; 4]> + '_ {
         (0..4).map(|i| {
             let mut result = [0; 4];
-            for j in 0..4 {
-                result[j] = self.get(j, i);
+            for (j, value) in result.iter_mut().enumerate() {
+                *value = self.get(j, i);
             }
             result
         })
@@ -60,15 +60,15 @@ impl Matrix {
 
     pub fn get_col(&self, col: usize) -> [u8; 4] {
         let mut result = [0; 4];
-        for i in 0..4 {
-            result[i] = self.get(i, col);
+        for (i, value) in result.iter_mut().enumerate() {
+            *value = self.get(i, col);
         }
         result
     }
 
     pub fn set_col(&mut self, col: usize, data: [u8; 4]) {
-        for i in 0..4 {
-            self.set(i, col, data[i]);
+        for (i, value) in data.into_iter().enumerate() {
+            self.set(i, col, value);
         }
     }
 
@@ -1,7 +1,12 @@
 mod chunk_reader;
 mod chunk_writer;
 mod matrix;
+mod phase_timer;
+mod pipeline;
+pub mod regular_file;
 
 pub use chunk_reader::ChunkReader;
-pub use chunk_writer::ChunkWriter;
+pub use chunk_writer::{unpadded_len, ChunkWriter};
 pub use matrix::Matrix;
+pub use phase_timer::{Phase, PhaseTimer, PhaseTimes};
+pub use pipeline::{Output, Pipeline};
@@ -0,0 +1,99 @@
+/*
+Time spent reading, ciphering and writing, added up over the batches of each run, so that a slow
+run can be told to be I/O-bound or compute-bound. The stages may run on different threads, so
+the totals are kept in atomics.
+ */
+use std::ops::AddAssign;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Stage of the ciphering loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Reading the input and padding it
+    Read,
+    /// Ciphering or deciphering the blocks, in parallel
+    Cipher,
+    /// Writing the output
+    Write,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 3] = [Phase::Read, Phase::Cipher, Phase::Write];
+
+    /// Name of the phase, as used in the metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Read => "read",
+            Phase::Cipher => "cipher",
+            Phase::Write => "write",
+        }
+    }
+}
+
+/// Total time spent in each phase. When a phase runs on several threads at once, the time of
+/// each thread is added, so the total can exceed the elapsed time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhaseTimes {
+    pub read: Duration,
+    pub cipher: Duration,
+    pub write: Duration,
+}
+
+impl PhaseTimes {
+    pub fn get(&self, phase: Phase) -> Duration {
+        match phase {
+            Phase::Read => self.read,
+            Phase::Cipher => self.cipher,
+            Phase::Write => self.write,
+        }
+    }
+}
+
+impl AddAssign for PhaseTimes {
+    fn add_assign(&mut self, other: Self) {
+        self.read += other.read;
+        self.cipher += other.cipher;
+        self.write += other.write;
+    }
+}
+
+/// Adds up the time spent in each phase, from any thread
+#[derive(Debug, Default)]
+pub struct PhaseTimer {
+    nanos: [AtomicU64; 3],
+}
+
+impl PhaseTimer {
+    /// Run `f` and add the time it takes to the phase
+    pub fn measure<T>(&self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let start_time = Instant::now();
+        let result = f();
+        self.add(phase, start_time.elapsed());
+        result
+    }
+
+    /// Add the time elapsed since `start_time` to the phase, and return the current instant,
+    /// from which the next phase can be timed
+    pub fn add_since(&self, phase: Phase, start_time: Instant) -> Instant {
+        let now = Instant::now();
+        self.add(phase, now - start_time);
+        now
+    }
+
+    fn add(&self, phase: Phase, duration: Duration) {
+        self.nanos[phase as usize].fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Return the time spent in each phase so far, and start again from zero
+    pub fn take(&self) -> PhaseTimes {
+        let take = |phase: Phase| {
+            Duration::from_nanos(self.nanos[phase as usize].swap(0, Ordering::Relaxed))
+        };
+        PhaseTimes {
+            read: take(Phase::Read),
+            cipher: take(Phase::Cipher),
+            write: take(Phase::Write),
+        }
+    }
+}
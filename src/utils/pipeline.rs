@@ -0,0 +1,368 @@
+/*
+Read, process and write stages of the ciphering loop. The stages either run one after the other
+on the current thread, or as a pipeline with a reader and a writer thread, so that the file I/O
+overlaps with the ciphering of the previous batch. The time spent in each stage is added up in
+the phase timer.
+ */
+use super::{ChunkReader, ChunkWriter, Phase, PhaseTimer};
+use crate::Result;
+use std::io::{Read, Write};
+use std::sync::mpsc;
+
+/// Blocks read from the input, of which the first `chunks_filled` hold data
+pub struct Batch<'a> {
+    blocks: &'a mut [[u8; 16]],
+    chunks_filled: usize,
+    last_chunk_size: usize,
+}
+
+impl<'a> Batch<'a> {
+    fn new(blocks: &'a mut [[u8; 16]]) -> Self {
+        Batch {
+            blocks,
+            chunks_filled: 0,
+            last_chunk_size: 0,
+        }
+    }
+
+    /// Fill the batch with the next blocks of the input, and return how many were read
+    fn read_from<R: Read>(&mut self, chunk_reader: &mut ChunkReader<R>) -> Result<usize> {
+        self.chunks_filled = chunk_reader.read_chunks(self.blocks.len(), self.blocks)?;
+        self.last_chunk_size = chunk_reader.last_chunk_size();
+        Ok(self.chunks_filled)
+    }
+
+    /// The blocks holding data, including any padding added to the last one
+    pub fn blocks(&self) -> &[[u8; 16]] {
+        &self.blocks[..self.chunks_filled]
+    }
+
+    pub fn blocks_mut(&mut self) -> &mut [[u8; 16]] {
+        &mut self.blocks[..self.chunks_filled]
+    }
+
+    /// The bytes read from the input, which end in a partial block at the end of the input
+    pub fn bytes(&self) -> &[u8] {
+        &self.blocks.as_flattened()[..self.bytes_filled()]
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        let bytes_filled = self.bytes_filled();
+        &mut self.blocks.as_flattened_mut()[..bytes_filled]
+    }
+
+    fn bytes_filled(&self) -> usize {
+        (self.chunks_filled - 1) * 16 + self.last_chunk_size
+    }
+}
+
+/// What is written to the output of each processed batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// The whole blocks, so that the writer can remove the padding from the last one
+    Blocks,
+    /// Only the bytes read, for the modes of operation without padding
+    Bytes,
+}
+
+impl Output {
+    fn write<W: Write>(&self, chunk_writer: &mut ChunkWriter<W>, batch: &Batch) -> Result<()> {
+        match self {
+            Output::Blocks => chunk_writer.write_chunks(batch.blocks())?,
+            Output::Bytes => chunk_writer.write_bytes(batch.bytes())?,
+        }
+        Ok(())
+    }
+}
+
+/// Buffers the input is read into, and how the stages are scheduled
+pub struct Pipeline {
+    buffer: Vec<[u8; 16]>,
+    /// Second buffer, which the reader fills while the first one is ciphered. It is only
+    /// allocated when pipelining.
+    back_buffer: Vec<[u8; 16]>,
+    pipelined: bool,
+    phase_timer: PhaseTimer,
+}
+
+impl Pipeline {
+    pub fn new(buffer_blocks: usize, pipelined: bool) -> Self {
+        Pipeline {
+            buffer: vec![[0; 16]; buffer_blocks],
+            back_buffer: match pipelined {
+                true => vec![[0; 16]; buffer_blocks],
+                false => Vec::new(),
+            },
+            pipelined,
+            phase_timer: PhaseTimer::default(),
+        }
+    }
+
+    /// Time spent in each stage, which the callers that do not go through `run` also add to
+    pub fn phase_timer(&self) -> &PhaseTimer {
+        &self.phase_timer
+    }
+
+    /// Read the input in batches, process each one in place on the current thread and write
+    /// it to the output, until the input ends or any stage fails. A batch that fails to be
+    /// processed is not written.
+    pub fn run<R, W, F>(
+        &mut self,
+        chunk_reader: &mut ChunkReader<R>,
+        chunk_writer: &mut ChunkWriter<W>,
+        output: Output,
+        process: F,
+    ) -> Result<()>
+    where
+        R: Read + Send,
+        W: Write + Send,
+        F: FnMut(&mut Batch) -> Result<()>,
+    {
+        match self.pipelined {
+            true => run_pipelined(
+                chunk_reader,
+                chunk_writer,
+                [&mut self.buffer, &mut self.back_buffer],
+                output,
+                &self.phase_timer,
+                process,
+            ),
+            false => run_sequential(
+                chunk_reader,
+                chunk_writer,
+                &mut self.buffer,
+                output,
+                &self.phase_timer,
+                process,
+            ),
+        }
+    }
+}
+
+fn run_sequential<R, W, F>(
+    chunk_reader: &mut ChunkReader<R>,
+    chunk_writer: &mut ChunkWriter<W>,
+    buffer: &mut [[u8; 16]],
+    output: Output,
+    phase_timer: &PhaseTimer,
+    mut process: F,
+) -> Result<()>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&mut Batch) -> Result<()>,
+{
+    let mut batch = Batch::new(buffer);
+    while phase_timer.measure(Phase::Read, || batch.read_from(chunk_reader))? > 0 {
+        phase_timer.measure(Phase::Cipher, || process(&mut batch))?;
+        phase_timer.measure(Phase::Write, || output.write(chunk_writer, &batch))?;
+    }
+    Ok(())
+}
+
+/// The reader thread fills a free buffer and sends it to the current thread, which processes
+/// it and sends it to the writer thread, which hands it back to the reader once written. The
+/// channels are bounded and the two buffers are all that circulates, so the reader is at most
+/// one batch ahead of the ciphering. When a stage fails or the input ends, it drops its
+/// channels, which stops the other stages.
+fn run_pipelined<R, W, F>(
+    chunk_reader: &mut ChunkReader<R>,
+    chunk_writer: &mut ChunkWriter<W>,
+    buffers: [&mut [[u8; 16]]; 2],
+    output: Output,
+    phase_timer: &PhaseTimer,
+    mut process: F,
+) -> Result<()>
+where
+    R: Read + Send,
+    W: Write + Send,
+    F: FnMut(&mut Batch) -> Result<()>,
+{
+    let (free_sender, free_receiver) = mpsc::sync_channel(buffers.len());
+    let (read_sender, read_receiver) = mpsc::sync_channel::<Result<Batch>>(1);
+    let (processed_sender, processed_receiver) = mpsc::sync_channel::<Batch>(1);
+    for buffer in buffers {
+        free_sender
+            .send(Batch::new(buffer))
+            .expect("the channel has room for every buffer");
+    }
+
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            for mut batch in free_receiver {
+                let read_result =
+                    match phase_timer.measure(Phase::Read, || batch.read_from(chunk_reader)) {
+                        Ok(0) => break,
+                        Ok(_) => Ok(batch),
+                        Err(e) => Err(e),
+                    };
+                let failed = read_result.is_err();
+                if read_sender.send(read_result).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        let writer = scope.spawn(move || -> Result<()> {
+            for batch in processed_receiver {
+                phase_timer.measure(Phase::Write, || output.write(chunk_writer, &batch))?;
+                // The reader is gone once the input has been read, so the buffer is not needed
+                free_sender.send(batch).ok();
+            }
+            Ok(())
+        });
+
+        let mut result = Ok(());
+        for read_result in read_receiver {
+            match read_result {
+                Ok(mut batch) => {
+                    result = phase_timer.measure(Phase::Cipher, || process(&mut batch));
+                    if result.is_err() || processed_sender.send(batch).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        drop(processed_sender);
+
+        let write_result = writer
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        result.and(write_result)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::padding::{Padding, Pkcs7};
+    use crate::Error;
+    use std::io::Cursor;
+
+    fn run(pipelined: bool, input: &[u8], output: Output) -> Vec<u8> {
+        let mut pipeline = Pipeline::new(3, pipelined);
+        let padding = (output == Output::Blocks).then_some(&Pkcs7 as &'static dyn Padding);
+        let mut chunk_reader = ChunkReader::new(input, 16, padding);
+        let mut result = Vec::new();
+        let mut chunk_writer = ChunkWriter::new(&mut result, None);
+
+        pipeline
+            .run(&mut chunk_reader, &mut chunk_writer, output, |batch| {
+                batch.bytes_mut().iter_mut().for_each(|byte| *byte ^= 0xff);
+                Ok(())
+            })
+            .unwrap();
+        chunk_writer.finish().unwrap();
+        drop(chunk_writer);
+        result
+    }
+
+    #[test]
+    fn test_pipelined_matches_sequential() {
+        for len in [0, 1, 16, 47, 48, 49, 1000] {
+            let input: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let expected: Vec<u8> = input.iter().map(|byte| byte ^ 0xff).collect();
+
+            for pipelined in [false, true] {
+                assert_eq!(run(pipelined, &input, Output::Bytes), expected);
+
+                let blocks = run(pipelined, &input, Output::Blocks);
+                assert_eq!(blocks.len(), (len / 16 + 1) * 16);
+                assert_eq!(blocks[..len], expected);
+            }
+        }
+    }
+
+    /// Output that fails once, when it has accepted a number of bytes
+    struct FailingWriter(Option<usize>);
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            match self.0 {
+                Some(room) if room < buf.len() => {
+                    self.0 = None;
+                    Err(std::io::Error::other("disk full"))
+                }
+                Some(room) => {
+                    self.0 = Some(room - buf.len());
+                    Ok(buf.len())
+                }
+                None => Ok(buf.len()),
+            }
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Input that fails once it has returned a number of bytes
+    struct FailingReader(usize);
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0 == 0 {
+                return Err(std::io::Error::other("read failed"));
+            }
+            let len = buf.len().min(self.0);
+            buf[..len].fill(1);
+            self.0 -= len;
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_pipelined_write_error() {
+        let input = vec![0; 1 << 20];
+        let mut pipeline = Pipeline::new(4, true);
+        let mut chunk_reader = ChunkReader::new(Cursor::new(&input), 16, None);
+        // More than the writer buffers, so that the error is not deferred to `finish`
+        let mut chunk_writer = ChunkWriter::new(FailingWriter(Some(100_000)), None);
+
+        let result = pipeline.run(&mut chunk_reader, &mut chunk_writer, Output::Bytes, |_| {
+            Ok(())
+        });
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_pipelined_read_error() {
+        let mut pipeline = Pipeline::new(4, true);
+        let mut chunk_reader = ChunkReader::new(FailingReader(100_000), 16, None);
+        let mut chunk_writer = ChunkWriter::new(Vec::new(), None);
+
+        let result = pipeline.run(&mut chunk_reader, &mut chunk_writer, Output::Bytes, |_| {
+            Ok(())
+        });
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_process_error() {
+        let input = vec![0; 1000];
+        for pipelined in [false, true] {
+            let mut pipeline = Pipeline::new(4, pipelined);
+            let mut chunk_reader = ChunkReader::new(Cursor::new(&input), 16, None);
+            let mut output = Vec::new();
+            let mut chunk_writer = ChunkWriter::new(&mut output, None);
+
+            // The third batch fails, so only the first two are written
+            let mut batches = 0;
+            let result = pipeline.run(&mut chunk_reader, &mut chunk_writer, Output::Bytes, |_| {
+                batches += 1;
+                match batches {
+                    3 => Err(Error::Unsupported("third batch".to_string())),
+                    _ => Ok(()),
+                }
+            });
+            assert!(matches!(result, Err(Error::Unsupported(_))));
+            chunk_writer.finish().unwrap();
+            drop(chunk_writer);
+            assert_eq!(output.len(), 2 * 4 * 16);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+/*
+Access to regular files other than streaming them: memory maps, so that the blocks are ciphered
+where the kernel pages them in instead of being copied through a buffer, and a preallocated
+output that several threads write at their own offsets. Pipes and special files support
+neither, so they keep going through the streaming path.
+ */
+use crate::Result;
+use memmap2::{Mmap, MmapMut};
+use std::fs::{File, OpenOptions};
+
+/// Whether the input file and the output file it is ciphered into can both be accessed at any
+/// offset: the input has to be a non-empty regular file, and the output either a regular file or
+/// a new one
+pub fn are_regular(input_file: &str, output_file: &str) -> bool {
+    let input_is_regular = std::fs::metadata(input_file)
+        .map(|metadata| metadata.is_file() && metadata.len() > 0)
+        .unwrap_or(false);
+    let output_is_regular = match std::fs::metadata(output_file) {
+        Ok(metadata) => metadata.is_file(),
+        Err(e) => e.kind() == std::io::ErrorKind::NotFound,
+    };
+    input_is_regular && output_is_regular
+}
+
+/// Create or truncate the output file, open for reading and writing, and resize it to `len`
+/// bytes
+pub fn create_output(output_file: &str, len: u64) -> Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(output_file)?;
+    file.set_len(len)?;
+    Ok(file)
+}
+
+/// Map the whole input file read-only
+pub fn map_input(input_file: &str) -> Result<Mmap> {
+    let file = File::open(input_file)?;
+    // SAFETY: the mapped file must not be modified while it is mapped, which the callers of the
+    // mmap path are warned about, as with any other memory map of a file
+    Ok(unsafe { Mmap::map(&file)? })
+}
+
+/// Create the output file with `len` bytes as in `create_output`, and map it read-write. The
+/// file is returned too, so that it can be shrunk once the map is dropped.
+pub fn map_output(output_file: &str, len: u64) -> Result<(File, MmapMut)> {
+    let file = create_output(output_file, len)?;
+    // SAFETY: as for the input, the file must not be modified by others while it is mapped
+    let map = unsafe { MmapMut::map_mut(&file)? };
+    Ok((file, map))
+}
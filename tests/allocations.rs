@@ -0,0 +1,97 @@
+/*
+Checks that ciphering a file does not allocate for every batch of blocks, by counting the
+allocations made by the whole process with a global allocator.
+ */
+use aes_rust::modes::ctr::CounterLayout;
+use aes_rust::modes::Mode;
+use aes_rust::padding::PaddingScheme;
+use aes_rust::{AESCipher, AESCipherConfig};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const KEY: [u8; 16] = [
+    0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c,
+];
+const BUFFER_BLOCKS: usize = 16;
+
+/// Allocations made while ciphering and deciphering `batches` batches of input
+fn count_allocations(cipher: &mut AESCipher, batches: usize) -> (usize, usize) {
+    let plain_text = vec![0x5a; batches * BUFFER_BLOCKS * 16];
+    let mut cipher_text = Vec::with_capacity(plain_text.len() + 1024);
+    let mut deciphered_text = Vec::with_capacity(plain_text.len() + 1024);
+
+    let start = ALLOCATIONS.load(Ordering::Relaxed);
+    cipher
+        .cipher(plain_text.as_slice(), &mut cipher_text)
+        .unwrap();
+    let cipher_allocations = ALLOCATIONS.load(Ordering::Relaxed) - start;
+
+    let start = ALLOCATIONS.load(Ordering::Relaxed);
+    cipher
+        .decipher_seekable(Cursor::new(&cipher_text), &mut deciphered_text)
+        .unwrap();
+    let decipher_allocations = ALLOCATIONS.load(Ordering::Relaxed) - start;
+
+    assert_eq!(deciphered_text, plain_text);
+    (cipher_allocations, decipher_allocations)
+}
+
+#[test]
+fn test_no_allocations_per_batch() {
+    let modes = [
+        Mode::Ecb,
+        Mode::Cbc,
+        Mode::Ctr(CounterLayout::Ctr128),
+        Mode::Gcm,
+    ];
+    for mode in modes {
+        for pipelined in [false, true] {
+            let config = AESCipherConfig {
+                n_threads: 2,
+                mode,
+                padding: PaddingScheme::Pkcs7,
+                buffer_blocks: BUFFER_BLOCKS,
+                pipelined,
+                ..Default::default()
+            };
+            let mut cipher = AESCipher::new(&KEY, config).unwrap();
+            // The first run allocates the buffers that are reused by the following ones
+            count_allocations(&mut cipher, 4);
+
+            let few = count_allocations(&mut cipher, 4);
+            let many = count_allocations(&mut cipher, 260);
+            // Rayon allocates a block of its job queue every 63 jobs sent to the pool from
+            // outside, so the count may grow a little, but far less than once per batch
+            for (few, many) in [(few.0, many.0), (few.1, many.1)] {
+                assert!(
+                    many < few + 256 / 16,
+                    "{:?} (pipelined: {}) allocates per batch: {} allocations for 4 batches, {} \
+                     for 260",
+                    mode,
+                    pipelined,
+                    few,
+                    many
+                );
+            }
+        }
+    }
+}